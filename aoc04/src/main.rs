@@ -1,16 +1,21 @@
-use std::collections::HashMap;
-use std::convert::TryFrom;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
 use std::fs::read_to_string;
+use std::io::{self, Write};
+use std::str::FromStr;
 
 type Error = &'static str;
 
 mod validators {
     use super::Error;
-    use std::collections::HashSet;
+    use std::collections::{HashMap, HashSet};
+    use std::fmt;
     use std::ops::RangeInclusive;
+    use std::str::FromStr;
     type Result<'a> = std::result::Result<&'a str, Error>;
 
-    pub fn in_range<'a>(input: &'a str, range: RangeInclusive<usize>) -> Result {
+    pub fn in_range(input: &str, range: RangeInclusive<usize>) -> Result<'_> {
         let num = input.parse::<usize>().map_err(|_| "not a number")?;
         if range.contains(&num) {
             Ok(input)
@@ -19,8 +24,8 @@ mod validators {
         }
     }
 
-    pub fn one_of<'a>(input: &'a str, set: &[&str]) -> Result<'a> {
-        let set: HashSet<&str> = set.iter().cloned().collect();
+    pub fn one_of<'a, S: AsRef<str>>(input: &'a str, set: &[S]) -> Result<'a> {
+        let set: HashSet<&str> = set.iter().map(|s| s.as_ref()).collect();
         if set.contains(input) {
             Ok(input)
         } else {
@@ -28,110 +33,725 @@ mod validators {
         }
     }
 
-    pub fn is_color(input: &str) -> Result {
-        if !input.starts_with('#') || !input.len() == 7 {
+    pub fn only_digits(input: &str) -> Result<'_> {
+        if !input.is_empty() && input.bytes().all(|b| b.is_ascii_digit()) {
+            Ok(input)
+        } else {
+            Err("value must be only digits")
+        }
+    }
+
+    pub fn is_color(input: &str) -> Result<'_> {
+        let without_prefix = input
+            .strip_prefix('#')
+            .ok_or("colors must be # followed by 6 hex digits")?;
+        if without_prefix.len() != 6 {
             return Err("colors must be # followed by 6 hex digits");
         }
-        let without_prefix = input.trim_start_matches('#');
-        if i64::from_str_radix(without_prefix, 16).is_ok() {
+        if without_prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
             Ok(input)
         } else {
             Err("invalid color number")
         }
     }
 
-    pub fn valid_height(input: &str) -> Result {
-        if input.ends_with("cm") {
-            in_range(input.trim_end_matches("cm"), 150..=193)
-        } else if input.ends_with("in") {
-            in_range(input.trim_end_matches("in"), 59..=76)
+    /// Any hex number after a #, regardless of length.
+    pub fn is_color_lenient(input: &str) -> Result<'_> {
+        let without_prefix = input
+            .strip_prefix('#')
+            .ok_or("colors must be # followed by hex digits")?;
+        if i64::from_str_radix(without_prefix, 16).is_ok() {
+            Ok(input)
         } else {
-            Err("height must end with in or cm")
+            Err("invalid color number")
+        }
+    }
+
+    pub fn with_units<'a, S: AsRef<str>>(
+        input: &'a str,
+        units: &[(S, RangeInclusive<usize>)],
+        strict: bool,
+    ) -> Result<'a> {
+        for (unit, range) in units {
+            if let Some(num) = input.strip_suffix(unit.as_ref()) {
+                if strict {
+                    only_digits(num)?;
+                }
+                in_range(num, range.clone())?;
+                return Ok(input);
+            }
         }
+        Err("value has an unknown unit")
     }
 
-    pub fn number_digits(input: &str, length: usize) -> Result {
+    pub fn number_digits(input: &str, length: usize) -> Result<'_> {
         if input.len() == length && input.parse::<u64>().is_ok() {
             Ok(input)
         } else {
             Err("value not in allowed")
         }
     }
+
+    /// How thoroughly field values are checked.
+    ///
+    /// `Presence` only requires that required fields exist, `Lenient` checks
+    /// values but accepts anything Rust can parse as a number (signs, colors
+    /// of any length), and `Strict` follows the rules to the letter.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Level {
+        Presence,
+        Lenient,
+        Strict,
+    }
+
+    impl FromStr for Level {
+        type Err = String;
+
+        fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+            match s {
+                "presence" => Ok(Level::Presence),
+                "lenient" => Ok(Level::Lenient),
+                "strict" => Ok(Level::Strict),
+                _ => Err(format!(
+                    "unknown level {:?}, expected presence, lenient or strict",
+                    s
+                )),
+            }
+        }
+    }
+
+    /// A single validator from a schema, along with its parameters.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum Rule {
+        Any,
+        Range(RangeInclusive<usize>),
+        OneOf(Vec<String>),
+        Color,
+        Digits(usize),
+        Units(Vec<(String, RangeInclusive<usize>)>),
+    }
+
+    impl Rule {
+        pub fn check<'a>(&self, input: &'a str, level: Level) -> Result<'a> {
+            let strict = match level {
+                Level::Presence => return Ok(input),
+                Level::Lenient => false,
+                Level::Strict => true,
+            };
+            match self {
+                Rule::Any => Ok(input),
+                Rule::Range(range) if strict => in_range(only_digits(input)?, range.clone()),
+                Rule::Range(range) => in_range(input, range.clone()),
+                Rule::OneOf(set) => one_of(input, set),
+                Rule::Color if strict => is_color(input),
+                Rule::Color => is_color_lenient(input),
+                Rule::Digits(length) if strict => number_digits(only_digits(input)?, *length),
+                Rule::Digits(length) => number_digits(input, *length),
+                Rule::Units(units) => with_units(input, units, strict),
+            }
+        }
+    }
+
+    fn parse_range(s: &str) -> std::result::Result<RangeInclusive<usize>, String> {
+        let mut parts = s.splitn(2, '-');
+        let min = parts.next().unwrap_or_default();
+        let max = parts
+            .next()
+            .ok_or_else(|| format!("range {:?} must be MIN-MAX", s))?;
+        let min = min
+            .parse()
+            .map_err(|_| format!("invalid range minimum {:?}", min))?;
+        let max = max
+            .parse()
+            .map_err(|_| format!("invalid range maximum {:?}", max))?;
+        if min > max {
+            return Err(format!("range {:?} has its minimum above its maximum", s));
+        }
+        Ok(min..=max)
+    }
+
+    fn write_range(f: &mut fmt::Formatter<'_>, range: &RangeInclusive<usize>) -> fmt::Result {
+        write!(f, "{}-{}", range.start(), range.end())
+    }
+
+    impl fmt::Display for Rule {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Rule::Any => write!(f, "any"),
+                Rule::Color => write!(f, "color"),
+                Rule::Range(range) => {
+                    write!(f, "range ")?;
+                    write_range(f, range)
+                }
+                Rule::OneOf(set) => write!(f, "one_of {}", set.join(" ")),
+                Rule::Digits(length) => write!(f, "digits {}", length),
+                Rule::Units(units) => {
+                    write!(f, "units")?;
+                    for (unit, range) in units {
+                        write!(f, " {}=", unit)?;
+                        write_range(f, range)?;
+                    }
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    impl FromStr for Rule {
+        type Err = String;
+
+        fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+            let mut words = s.split_whitespace();
+            let kind = words.next().ok_or("missing validator")?;
+            let params: Vec<&str> = words.collect();
+            let single = || match params.as_slice() {
+                [param] => Ok(*param),
+                _ => Err(format!("{} takes exactly one parameter", kind)),
+            };
+            let rule = match kind {
+                "any" => Rule::Any,
+                "color" => Rule::Color,
+                "range" => Rule::Range(parse_range(single()?)?),
+                "digits" => Rule::Digits(
+                    single()?
+                        .parse()
+                        .map_err(|_| "digits takes a length".to_string())?,
+                ),
+                "one_of" if !params.is_empty() => {
+                    Rule::OneOf(params.iter().map(|p| p.to_string()).collect())
+                }
+                "units" if !params.is_empty() => {
+                    let mut units = vec![];
+                    for param in &params {
+                        let mut parts = param.splitn(2, '=');
+                        let unit = parts.next().unwrap_or_default();
+                        let range = parts
+                            .next()
+                            .ok_or_else(|| format!("unit {:?} must be UNIT=MIN-MAX", param))?;
+                        if unit.is_empty() {
+                            return Err(format!("unit {:?} has no name", param));
+                        }
+                        units.push((unit.to_string(), parse_range(range)?));
+                    }
+                    Rule::Units(units)
+                }
+                "one_of" | "units" => return Err(format!("{} needs parameters", kind)),
+                _ => return Err(format!("unknown validator {:?}", kind)),
+            };
+            if matches!(rule, Rule::Any | Rule::Color) && !params.is_empty() {
+                return Err(format!("{} takes no parameters", kind));
+            }
+            Ok(rule)
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct Field {
+        pub name: String,
+        pub required: bool,
+        pub rule: Rule,
+    }
+
+    /// The set of fields a document type must (or may) have, one per line:
+    ///
+    /// ```text
+    /// # field  presence  validator  parameters
+    /// byr required range 1920-2002
+    /// hgt required units cm=150-193 in=59-76
+    /// cid optional any
+    /// ```
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct Schema {
+        pub fields: Vec<Field>,
+    }
+
+    impl FromStr for Schema {
+        type Err = String;
+
+        fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+            let mut fields: Vec<Field> = vec![];
+            for (i, line) in s.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let lineno = i + 1;
+                let mut words = line.split_whitespace();
+                let name = words.next().unwrap_or_default();
+                let required = match words.next() {
+                    Some("required") => true,
+                    Some("optional") => false,
+                    _ => {
+                        return Err(format!(
+                            "line {}: expected required or optional after {:?}",
+                            lineno, name
+                        ))
+                    }
+                };
+                let rule = words
+                    .collect::<Vec<_>>()
+                    .join(" ")
+                    .parse()
+                    .map_err(|e| format!("line {}: {}", lineno, e))?;
+                if fields.iter().any(|f| f.name == name) {
+                    return Err(format!("line {}: duplicate field {:?}", lineno, name));
+                }
+                fields.push(Field {
+                    name: name.to_string(),
+                    required,
+                    rule,
+                });
+            }
+            Ok(Schema { fields })
+        }
+    }
+
+    /// Why a single field of a document didn't pass its schema.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum FieldError<'a> {
+        Missing {
+            field: String,
+        },
+        Invalid {
+            field: String,
+            rule: Rule,
+            value: &'a str,
+            reason: Error,
+        },
+    }
+
+    impl FieldError<'_> {
+        pub fn field(&self) -> &str {
+            match self {
+                FieldError::Missing { field } | FieldError::Invalid { field, .. } => field,
+            }
+        }
+
+        #[cfg(test)]
+        pub fn reason(&self) -> Error {
+            match self {
+                FieldError::Missing { .. } => "missing field",
+                FieldError::Invalid { reason, .. } => reason,
+            }
+        }
+    }
+
+    impl fmt::Display for FieldError<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                FieldError::Missing { field } => write!(f, "{}: missing", field),
+                FieldError::Invalid {
+                    field,
+                    rule,
+                    value,
+                    reason,
+                } => write!(f, "{}: {:?} breaks `{}` ({})", field, value, rule, reason),
+            }
+        }
+    }
+
+    impl Schema {
+        /// Checks every field in the schema, collecting all the failures
+        /// rather than stopping at the first.
+        pub fn check<'a>(
+            &self,
+            fields: &HashMap<&str, &'a str>,
+            level: Level,
+        ) -> Vec<FieldError<'a>> {
+            let mut errors = vec![];
+            for field in &self.fields {
+                match fields.get(field.name.as_str()) {
+                    Some(value) => {
+                        if let Err(reason) = field.rule.check(value, level) {
+                            errors.push(FieldError::Invalid {
+                                field: field.name.clone(),
+                                rule: field.rule.clone(),
+                                value,
+                                reason,
+                            });
+                        }
+                    }
+                    None if field.required => errors.push(FieldError::Missing {
+                        field: field.name.clone(),
+                    }),
+                    None => {}
+                }
+            }
+            errors
+        }
+
+        #[cfg(test)]
+        pub fn validate(
+            &self,
+            fields: &HashMap<&str, &str>,
+            level: Level,
+        ) -> std::result::Result<(), Error> {
+            match self.check(fields, level).first() {
+                Some(error) => Err(error.reason()),
+                None => Ok(()),
+            }
+        }
+    }
 }
 
-#[allow(dead_code)]
+use validators::{FieldError, Level, Schema};
+
+/// The schema for passports, used when no other schema file is given.
+const PASSPORT_SCHEMA: &str = include_str!("../passport.schema");
+
 #[derive(Debug)]
 struct Passport<'a> {
-    byr: &'a str,
-    ecl: &'a str,
-    eyr: &'a str,
-    hcl: &'a str,
-    hgt: &'a str,
-    iyr: &'a str,
-    pid: &'a str,
-    cid: Option<&'a str>,
+    fields: HashMap<&'a str, &'a str>,
 }
 
-// byr (Birth Year) - four digits; at least 1920 and at most 2002.
-// iyr (Issue Year) - four digits; at least 2010 and at most 2020.
-// eyr (Expiration Year) - four digits; at least 2020 and at most 2030.
-// hgt (Height) - a number followed by either cm or in:
-// If cm, the number must be at least 150 and at most 193.
-// If in, the number must be at least 59 and at most 76.
-// hcl (Hair Color) - a # followed by exactly six characters 0-9 or a-f.
-// ecl (Eye Color) - exactly one of: amb blu brn gry grn hzl oth.
-// pid (Passport ID) - a nine-digit number, including leading zeroes.
-// cid (Country ID) - ignored, missing or not.
-
-impl<'a> TryFrom<&'a str> for Passport<'a> {
-    type Error = &'static str;
-
-    fn try_from(value: &'a str) -> Result<Passport<'a>, Self::Error> {
-        use validators::{in_range, is_color, number_digits, one_of, valid_height};
+impl<'a> Passport<'a> {
+    fn parse(value: &'a str) -> Result<Passport<'a>, Error> {
         let mut fields = HashMap::new();
-        for entry in value.trim().split_whitespace() {
+        for entry in value.split_whitespace() {
             let mid = entry.find(':');
             let mid = mid.ok_or("Missing : to delimit fields")?;
             let (key, val) = entry.split_at(mid);
             fields.insert(key, &val[1..]);
         }
-        for field in &["byr", "ecl", "eyr", "hcl", "hgt", "iyr", "pid"] {
-            fields.get(field).ok_or("missing field")?;
-        }
-        let passport = Passport {
-            byr: in_range(fields["byr"], 1920..=2002)?,
-            ecl: one_of(
-                fields["ecl"],
-                &["amb", "blu", "brn", "gry", "grn", "hzl", "oth"],
-            )?,
-            eyr: in_range(fields["eyr"], 2020..=2030)?,
-            hcl: is_color(fields["hcl"])?,
-            hgt: valid_height(fields["hgt"])?,
-            iyr: in_range(fields["iyr"], 2010..=2020)?,
-            pid: number_digits(fields["pid"], 9)?,
-            cid: fields.get("cid").copied(),
-        };
+        Ok(Passport { fields })
+    }
+
+    #[cfg(test)]
+    fn validate(value: &'a str, schema: &Schema, level: Level) -> Result<Passport<'a>, Error> {
+        let passport = Passport::parse(value)?;
+        schema.validate(&passport.fields, level)?;
         Ok(passport)
     }
+
+    /// Like `validate`, but reports every failing field.
+    fn check(value: &'a str, schema: &Schema, level: Level) -> Result<Passport<'a>, Checked<'a>> {
+        let passport = Passport::parse(value).map_err(Checked::Malformed)?;
+        let errors = schema.check(&passport.fields, level);
+        if errors.is_empty() {
+            Ok(passport)
+        } else {
+            Err(Checked::Fields(errors))
+        }
+    }
 }
 
-fn main() {
-    let input = read_to_string("input.txt").unwrap();
-    let entries: Vec<_> = input.trim().split("\n\n").map(Passport::try_from).collect();
+/// The ways a passport can fail `Passport::check`.
+#[derive(Debug, PartialEq, Eq)]
+enum Checked<'a> {
+    Malformed(Error),
+    Fields(Vec<FieldError<'a>>),
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct FieldCounts {
+    missing: usize,
+    invalid: usize,
+}
+
+/// Failures by field across a whole batch of passports.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Summary {
+    total: usize,
+    valid: usize,
+    malformed: usize,
+    fields: BTreeMap<String, FieldCounts>,
+}
+
+impl Summary {
+    fn add(&mut self, result: &Result<Passport, Checked>) {
+        self.total += 1;
+        match result {
+            Ok(_) => self.valid += 1,
+            Err(Checked::Malformed(_)) => self.malformed += 1,
+            Err(Checked::Fields(errors)) => {
+                for error in errors {
+                    let counts = self.fields.entry(error.field().to_string()).or_default();
+                    match error {
+                        FieldError::Missing { .. } => counts.missing += 1,
+                        FieldError::Invalid { .. } => counts.invalid += 1,
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Passports: {}", self.total)?;
+        writeln!(f, "Valid: {}", self.valid)?;
+        writeln!(f, "Malformed: {}", self.malformed)?;
+        write!(f, "{:<8}{:>9}{:>9}", "field", "missing", "invalid")?;
+        for (field, counts) in &self.fields {
+            write!(
+                f,
+                "\n{:<8}{:>9}{:>9}",
+                field, counts.missing, counts.invalid
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Export formats for `--export`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Csv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "unknown export format {:?}, expected csv or json",
+                s
+            )),
+        }
+    }
+}
+
+/// A passport with its fields normalised for export: years as integers and
+/// height split into value and unit. Fields that are missing or can't be
+/// normalised are `None`.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Record<'a> {
+    status: &'static str,
+    byr: Option<u32>,
+    iyr: Option<u32>,
+    eyr: Option<u32>,
+    hgt_value: Option<u32>,
+    hgt_unit: Option<&'a str>,
+    hcl: Option<&'a str>,
+    ecl: Option<&'a str>,
+    pid: Option<&'a str>,
+    cid: Option<&'a str>,
+}
+
+const RECORD_COLUMNS: [&str; 10] = [
+    "status",
+    "byr",
+    "iyr",
+    "eyr",
+    "hgt_value",
+    "hgt_unit",
+    "hcl",
+    "ecl",
+    "pid",
+    "cid",
+];
+
+/// A single exported value, so CSV and JSON can quote them differently.
+enum Value<'a> {
+    Null,
+    Number(u32),
+    Text(&'a str),
+}
+
+impl<'a> From<Option<u32>> for Value<'a> {
+    fn from(value: Option<u32>) -> Self {
+        value.map_or(Value::Null, Value::Number)
+    }
+}
+
+impl<'a> From<Option<&'a str>> for Value<'a> {
+    fn from(value: Option<&'a str>) -> Self {
+        value.map_or(Value::Null, Value::Text)
+    }
+}
+
+impl<'a> Record<'a> {
+    fn new(entry: &'a str, result: &Result<Passport, Checked>) -> Record<'a> {
+        let status = match result {
+            Ok(_) => "valid",
+            Err(Checked::Fields(_)) => "invalid",
+            Err(Checked::Malformed(_)) => "malformed",
+        };
+        let passport = match Passport::parse(entry) {
+            Ok(passport) => passport,
+            Err(_) => {
+                return Record {
+                    status,
+                    ..Record::default()
+                }
+            }
+        };
+        let get = |field| passport.fields.get(field).copied();
+        let year = |field| get(field).and_then(|v: &str| v.parse().ok());
+        let (hgt_value, hgt_unit) = match get("hgt") {
+            Some(hgt) => {
+                let split = hgt.find(|c: char| !c.is_ascii_digit()).unwrap_or(hgt.len());
+                let (value, unit) = hgt.split_at(split);
+                (value.parse().ok(), Some(unit).filter(|u| !u.is_empty()))
+            }
+            None => (None, None),
+        };
+        Record {
+            status,
+            byr: year("byr"),
+            iyr: year("iyr"),
+            eyr: year("eyr"),
+            hgt_value,
+            hgt_unit,
+            hcl: get("hcl"),
+            ecl: get("ecl"),
+            pid: get("pid"),
+            cid: get("cid"),
+        }
+    }
+
+    fn values(&self) -> [Value<'a>; 10] {
+        [
+            Value::Text(self.status),
+            self.byr.into(),
+            self.iyr.into(),
+            self.eyr.into(),
+            self.hgt_value.into(),
+            self.hgt_unit.into(),
+            self.hcl.into(),
+            self.ecl.into(),
+            self.pid.into(),
+            self.cid.into(),
+        ]
+    }
+}
+
+fn write_csv<W: Write>(out: &mut W, records: &[Record]) -> io::Result<()> {
+    writeln!(out, "{}", RECORD_COLUMNS.join(","))?;
+    for record in records {
+        let row: Vec<String> = record
+            .values()
+            .iter()
+            .map(|value| match value {
+                Value::Null => String::new(),
+                Value::Number(n) => n.to_string(),
+                Value::Text(s) if s.contains([',', '"', '\n']) => {
+                    format!("\"{}\"", s.replace('"', "\"\""))
+                }
+                Value::Text(s) => s.to_string(),
+            })
+            .collect();
+        writeln!(out, "{}", row.join(","))?;
+    }
+    Ok(())
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn write_json<W: Write>(out: &mut W, records: &[Record]) -> io::Result<()> {
+    writeln!(out, "[")?;
+    for (i, record) in records.iter().enumerate() {
+        let fields: Vec<String> = RECORD_COLUMNS
+            .iter()
+            .zip(record.values().iter())
+            .map(|(column, value)| {
+                let value = match value {
+                    Value::Null => "null".to_string(),
+                    Value::Number(n) => n.to_string(),
+                    Value::Text(s) => json_string(s),
+                };
+                format!("\"{}\": {}", column, value)
+            })
+            .collect();
+        let separator = if i + 1 < records.len() { "," } else { "" };
+        writeln!(out, "  {{{}}}{}", fields.join(", "), separator)?;
+    }
+    writeln!(out, "]")
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut input_path = "input.txt".to_string();
+    let mut schema_path = None;
+    let mut report = false;
+    let mut level = Level::Strict;
+    let mut export = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--schema" => schema_path = Some(args.next().ok_or("--schema needs a path")?),
+            "--report" => report = true,
+            "--export" => export = Some(args.next().ok_or("--export needs a format")?.parse()?),
+            "--level" => level = args.next().ok_or("--level needs a value")?.parse()?,
+            _ => input_path = arg,
+        }
+    }
+
+    let schema: Schema = match schema_path {
+        Some(path) => read_to_string(path)?.parse()?,
+        None => PASSPORT_SCHEMA.parse()?,
+    };
+    let input = read_to_string(input_path)?;
+    let raw: Vec<_> = input.trim().split("\n\n").collect();
+    let entries: Vec<_> = raw
+        .iter()
+        .map(|entry| Passport::check(entry, &schema, level))
+        .collect();
+
+    if let Some(format) = export {
+        let records: Vec<_> = raw
+            .iter()
+            .zip(entries.iter())
+            .map(|(entry, result)| Record::new(entry, result))
+            .collect();
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        match format {
+            Format::Csv => write_csv(&mut out, &records)?,
+            Format::Json => write_json(&mut out, &records)?,
+        }
+        return Ok(());
+    }
+
     let values_valid_count = entries.iter().filter(|p| p.is_ok()).count();
     println!("Values valid: {}", values_valid_count);
+
+    if report {
+        let mut summary = Summary::default();
+        for (i, result) in entries.iter().enumerate() {
+            summary.add(result);
+            match result {
+                Ok(_) => {}
+                Err(Checked::Malformed(reason)) => println!("#{}: {}", i + 1, reason),
+                Err(Checked::Fields(errors)) => {
+                    for error in errors {
+                        println!("#{}: {}", i + 1, error);
+                    }
+                }
+            }
+        }
+        println!();
+        println!("{}", summary);
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use super::validators::{Field, FieldError, Rule};
     use super::*;
 
-    fn passport<'a>(input: &'a str) -> Passport<'a> {
-        Passport::try_from(input).unwrap()
-    }
-
     #[test]
     fn test_passport_valid() {
+        let schema: Schema = PASSPORT_SCHEMA.parse().unwrap();
+        let passport = |input| Passport::validate(input, &schema, Level::Strict);
         let result = passport(
             "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd\n\
              byr:1937 iyr:2017 cid:147 hgt:183cm",
@@ -143,7 +763,7 @@ mod tests {
             "iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884\n\
              hcl:#cfa07d byr:1929",
         );
-        assert!(!result.is_err());
+        assert!(result.is_err());
 
         let result = passport(
             "hcl:#ae17e1 iyr:2013\n\
@@ -157,6 +777,226 @@ mod tests {
             "hcl:#cfa07d eyr:2025 pid:166559648\n\
              iyr:2011 ecl:brn hgt:59in",
         );
-        assert!(!result.is_err());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_schema_parse() {
+        let schema: Schema = "# comment\n\
+                              hgt required units cm=150-193 in=59-76\n\
+                              cid optional any"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            schema.fields,
+            vec![
+                Field {
+                    name: "hgt".to_string(),
+                    required: true,
+                    rule: Rule::Units(vec![
+                        ("cm".to_string(), 150..=193),
+                        ("in".to_string(), 59..=76)
+                    ]),
+                },
+                Field {
+                    name: "cid".to_string(),
+                    required: false,
+                    rule: Rule::Any,
+                },
+            ]
+        );
+
+        assert!("byr sometimes any".parse::<Schema>().is_err());
+        assert!("byr required range 10".parse::<Schema>().is_err());
+        assert_eq!(
+            "# years\nbyr required range 2002-1920".parse::<Schema>(),
+            Err("line 2: range \"2002-1920\" has its minimum above its maximum".to_string())
+        );
+        assert_eq!(
+            "hgt required units cm=150-193 =1-2".parse::<Schema>(),
+            Err("line 1: unit \"=1-2\" has no name".to_string())
+        );
+        assert!("byr required range 1920-1920".parse::<Schema>().is_ok());
+        assert!("byr required bogus".parse::<Schema>().is_err());
+        assert!("a required any\na optional any".parse::<Schema>().is_err());
+    }
+
+    #[test]
+    fn test_custom_schema() {
+        let schema: Schema = "name required one_of alice bob\n\
+                              age optional range 0-130"
+            .parse()
+            .unwrap();
+        assert!(Passport::validate("name:bob", &schema, Level::Strict).is_ok());
+        assert!(Passport::validate("name:bob age:40", &schema, Level::Strict).is_ok());
+        assert!(Passport::validate("name:eve", &schema, Level::Strict).is_err());
+        assert!(Passport::validate("age:40", &schema, Level::Strict).is_err());
+        assert!(Passport::validate("name:alice age:200", &schema, Level::Strict).is_err());
+    }
+
+    #[test]
+    fn test_check_reports_every_field() {
+        let schema: Schema = PASSPORT_SCHEMA.parse().unwrap();
+        let result = Passport::check(
+            "eyr:1972 cid:100\n\
+             hcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 byr:1926",
+            &schema,
+            Level::Strict,
+        );
+        let errors = match result {
+            Err(Checked::Fields(errors)) => errors,
+            other => panic!("unexpected {:?}", other),
+        };
+        let fields: Vec<_> = errors.iter().map(|e| e.field()).collect();
+        assert_eq!(fields, vec!["eyr", "hgt", "pid"]);
+        assert_eq!(
+            errors[0],
+            FieldError::Invalid {
+                field: "eyr".to_string(),
+                rule: Rule::Range(2020..=2030),
+                value: "1972",
+                reason: "number not in range",
+            }
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            "eyr: \"1972\" breaks `range 2020-2030` (number not in range)"
+        );
+
+        let result = Passport::check("iyr:2019 hcl:#602927 eyr:1967", &schema, Level::Strict);
+        let errors = match result {
+            Err(Checked::Fields(errors)) => errors,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(
+            errors[0],
+            FieldError::Missing {
+                field: "byr".to_string()
+            }
+        );
+
+        assert_eq!(
+            Passport::check("byr", &schema, Level::Strict).unwrap_err(),
+            Checked::Malformed("Missing : to delimit fields")
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let schema: Schema = "a required digits 1\nb optional digits 1".parse().unwrap();
+        let mut summary = Summary::default();
+        for entry in &["a:1", "a:12 b:x", "b:1", "b:22", "nope"] {
+            summary.add(&Passport::check(entry, &schema, Level::Strict));
+        }
+        assert_eq!(summary.total, 5);
+        assert_eq!(summary.valid, 1);
+        assert_eq!(summary.malformed, 1);
+        assert_eq!(
+            summary.fields["a"],
+            FieldCounts {
+                missing: 2,
+                invalid: 1
+            }
+        );
+        assert_eq!(
+            summary.fields["b"],
+            FieldCounts {
+                missing: 0,
+                invalid: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_levels() {
+        let schema: Schema = PASSPORT_SCHEMA.parse().unwrap();
+        let check = |input, level| Passport::validate(input, &schema, level).is_ok();
+
+        let incomplete = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd byr:1937 iyr:2017";
+        assert!(!check(incomplete, Level::Presence));
+
+        let bad_values = "ecl:zzz pid:1 eyr:1 hcl:z byr:1 iyr:1 hgt:1";
+        assert!(check(bad_values, Level::Presence));
+        assert!(!check(bad_values, Level::Lenient));
+
+        let short_color = "ecl:gry pid:860033327 eyr:2020 hcl:#fff byr:1937 iyr:2017 hgt:183cm";
+        assert!(check(short_color, Level::Lenient));
+        assert!(!check(short_color, Level::Strict));
+
+        let signed = "ecl:gry pid:+60033327 eyr:2020 hcl:#fffffd byr:+1937 iyr:2017 hgt:183cm";
+        assert!(check(signed, Level::Lenient));
+        assert!(!check(signed, Level::Strict));
+    }
+
+    #[test]
+    fn test_is_color() {
+        use super::validators::is_color;
+        assert!(is_color("#123abc").is_ok());
+        assert!(is_color("#123abz").is_err());
+        assert!(is_color("123abc").is_err());
+        assert!(is_color("#123abcd").is_err());
+        assert!(is_color("#-12abc").is_err());
+    }
+
+    #[test]
+    fn test_record() {
+        let schema: Schema = PASSPORT_SCHEMA.parse().unwrap();
+        let entry = "hcl:#623a2f eyr:2028 pid:087499704 hgt:74in ecl:grn iyr:2012 byr:1980";
+        let result = Passport::check(entry, &schema, Level::Strict);
+        assert_eq!(
+            Record::new(entry, &result),
+            Record {
+                status: "valid",
+                byr: Some(1980),
+                iyr: Some(2012),
+                eyr: Some(2028),
+                hgt_value: Some(74),
+                hgt_unit: Some("in"),
+                hcl: Some("#623a2f"),
+                ecl: Some("grn"),
+                pid: Some("087499704"),
+                cid: None,
+            }
+        );
+
+        let entry = "hgt:59 byr:xx";
+        let result = Passport::check(entry, &schema, Level::Strict);
+        let record = Record::new(entry, &result);
+        assert_eq!(record.status, "invalid");
+        assert_eq!(record.byr, None);
+        assert_eq!((record.hgt_value, record.hgt_unit), (Some(59), None));
+
+        let entry = "oops";
+        let result = Passport::check(entry, &schema, Level::Strict);
+        assert_eq!(Record::new(entry, &result).status, "malformed");
+    }
+
+    #[test]
+    fn test_export() {
+        let records = vec![Record {
+            status: "invalid",
+            byr: Some(1980),
+            hgt_value: Some(150),
+            hgt_unit: Some("cm"),
+            hcl: Some("a,\"b\""),
+            ..Record::default()
+        }];
+
+        let mut csv = vec![];
+        write_csv(&mut csv, &records).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "status,byr,iyr,eyr,hgt_value,hgt_unit,hcl,ecl,pid,cid\n\
+             invalid,1980,,,150,cm,\"a,\"\"b\"\"\",,,\n"
+        );
+
+        let mut json = vec![];
+        write_json(&mut json, &records).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "[\n  {\"status\": \"invalid\", \"byr\": 1980, \"iyr\": null, \"eyr\": null, \
+             \"hgt_value\": 150, \"hgt_unit\": \"cm\", \"hcl\": \"a,\\\"b\\\"\", \
+             \"ecl\": null, \"pid\": null, \"cid\": null}\n]\n"
+        );
     }
 }
@@ -1,35 +1,563 @@
-pub fn decode(seat: &str) -> usize {
-    let binary: String = seat
-        .chars()
-        .map(|c| match c {
-            'B' | 'R' => '1',
-            'F' | 'L' => '0',
-            _ => c,
+use std::fmt;
+use std::io::{self, Write};
+
+/// A seat on a plane, by row (front to back) and column (left to right).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Seat {
+    pub row: usize,
+    pub column: usize,
+}
+
+/// The layout of an aircraft, as the number of boarding-pass characters used
+/// to pick a row (`F`/`B`) and then a column (`L`/`R`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Plane {
+    row_bits: u32,
+    column_bits: u32,
+}
+
+impl Default for Plane {
+    fn default() -> Self {
+        Plane {
+            row_bits: 7,
+            column_bits: 3,
+        }
+    }
+}
+
+/// Why a plane layout can't be used.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlaneError {
+    /// Rows and columns are picked by halving, so each count must be a power
+    /// of two.
+    NotPowerOfTwo(usize),
+    /// More boarding-pass characters than `Plane::MAX_BITS`.
+    TooLarge { bits: u32 },
+}
+
+impl fmt::Display for PlaneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaneError::NotPowerOfTwo(n) => write!(f, "{} is not a power of two", n),
+            PlaneError::TooLarge { bits } => write!(
+                f,
+                "{} boarding-pass characters is more than the {} supported",
+                bits,
+                Plane::MAX_BITS
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PlaneError {}
+
+/// Why a boarding pass couldn't be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PassError {
+    Length { expected: usize, found: usize },
+    Character { position: usize, found: char },
+}
+
+impl fmt::Display for PassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PassError::Length { expected, found } => {
+                write!(f, "expected {} characters, found {}", expected, found)
+            }
+            PassError::Character { position, found } => {
+                write!(f, "unexpected {:?} at position {}", found, position + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PassError {}
+
+fn to_binary(part: &str, offset: usize, zero: char, one: char) -> Result<usize, PassError> {
+    let mut value = 0;
+    for (i, c) in part.chars().enumerate() {
+        let bit = match c {
+            c if c == one => 1,
+            c if c == zero => 0,
+            found => {
+                return Err(PassError::Character {
+                    position: offset + i,
+                    found,
+                })
+            }
+        };
+        value = value << 1 | bit;
+    }
+    Ok(value)
+}
+
+fn from_binary(value: usize, bits: u32, zero: char, one: char) -> String {
+    (0..bits)
+        .rev()
+        .map(|bit| if value >> bit & 1 == 1 { one } else { zero })
+        .collect()
+}
+
+impl Plane {
+    /// The most boarding-pass characters a plane can use, which keeps a
+    /// manifest of every seat to a reasonable size.
+    pub const MAX_BITS: u32 = 20;
+
+    pub fn new(row_bits: u32, column_bits: u32) -> Result<Self, PlaneError> {
+        let bits = row_bits.saturating_add(column_bits);
+        if bits > Plane::MAX_BITS {
+            return Err(PlaneError::TooLarge { bits });
+        }
+        Ok(Plane {
+            row_bits,
+            column_bits,
         })
-        .collect();
-    usize::from_str_radix(&binary, 2).unwrap()
+    }
+
+    /// A plane with `rows` rows of `columns` seats.
+    pub fn with_size(rows: usize, columns: usize) -> Result<Self, PlaneError> {
+        let bits = |n: usize| match n.is_power_of_two() {
+            true => Ok(n.trailing_zeros()),
+            false => Err(PlaneError::NotPowerOfTwo(n)),
+        };
+        Plane::new(bits(rows)?, bits(columns)?)
+    }
+
+    pub fn rows(&self) -> usize {
+        1 << self.row_bits
+    }
+
+    pub fn columns(&self) -> usize {
+        1 << self.column_bits
+    }
+
+    pub fn seats(&self) -> usize {
+        self.rows() * self.columns()
+    }
+
+    pub fn id(&self, seat: Seat) -> usize {
+        seat.row * self.columns() + seat.column
+    }
+
+    pub fn seat(&self, id: usize) -> Seat {
+        Seat {
+            row: id / self.columns(),
+            column: id % self.columns(),
+        }
+    }
+
+    pub fn decode(&self, pass: &str) -> Result<Seat, PassError> {
+        let expected = (self.row_bits + self.column_bits) as usize;
+        let found = pass.chars().count();
+        if found != expected {
+            return Err(PassError::Length { expected, found });
+        }
+        let row_bits = self.row_bits as usize;
+        let split = pass
+            .char_indices()
+            .nth(row_bits)
+            .map_or(pass.len(), |(i, _)| i);
+        let (row, column) = pass.split_at(split);
+        Ok(Seat {
+            row: to_binary(row, 0, 'F', 'B')?,
+            column: to_binary(column, row_bits, 'L', 'R')?,
+        })
+    }
+
+    pub fn decode_id(&self, pass: &str) -> Result<usize, PassError> {
+        self.decode(pass).map(|seat| self.id(seat))
+    }
+
+    /// Whether a seat is on this plane.
+    pub fn contains(&self, seat: Seat) -> bool {
+        seat.row < self.rows() && seat.column < self.columns()
+    }
+
+    /// The boarding pass for a seat, or `None` if it isn't on this plane.
+    pub fn encode(&self, seat: Seat) -> Option<String> {
+        if !self.contains(seat) {
+            return None;
+        }
+        Some(
+            from_binary(seat.row, self.row_bits, 'F', 'B')
+                + &from_binary(seat.column, self.column_bits, 'L', 'R'),
+        )
+    }
+
+    pub fn encode_id(&self, id: usize) -> Option<String> {
+        self.encode(self.seat(id))
+    }
+}
+
+pub fn decode(seat: &str) -> Result<usize, PassError> {
+    Plane::default().decode_id(seat)
+}
+
+/// Where an empty seat sits relative to the occupied ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Gap {
+    /// In front of every occupied seat, e.g. missing front rows.
+    Front,
+    /// Between occupied seats.
+    Interior,
+    /// Behind every occupied seat, e.g. missing back rows.
+    Back,
+}
+
+/// Every boarding pass in a list, with the seats they occupy. Line numbers
+/// start at 1.
+#[derive(Debug)]
+pub struct Manifest {
+    plane: Plane,
+    occupied: Vec<Option<usize>>,
+    pub invalid: Vec<(usize, PassError)>,
+    /// `(line, first_line, seat)` for each pass that repeats an earlier one.
+    pub duplicates: Vec<(usize, usize, Seat)>,
 }
 
-fn main() {
-    let input = std::fs::read_to_string("input.txt").unwrap();
-    let mut seat_ids: Vec<_> = input.lines().map(decode).collect();
-    seat_ids.sort();
-    for (i, seat) in seat_ids.iter().skip(1).enumerate() {
-        if seat - seat_ids[i] > 1 {
-            print!("Seat: {}", seat - 1);
-            break;
+impl Manifest {
+    pub fn new(plane: Plane, input: &str) -> Self {
+        let mut manifest = Manifest {
+            plane,
+            occupied: vec![None; plane.seats()],
+            invalid: vec![],
+            duplicates: vec![],
+        };
+        for (i, pass) in input.lines().enumerate() {
+            let line = i + 1;
+            match plane.decode_id(pass) {
+                Ok(id) => match manifest.occupied[id] {
+                    Some(first_line) => {
+                        manifest.duplicates.push((line, first_line, plane.seat(id)))
+                    }
+                    None => manifest.occupied[id] = Some(line),
+                },
+                Err(e) => manifest.invalid.push((line, e)),
+            }
         }
+        manifest
+    }
+
+    /// Whether a seat is taken. Seats off the plane never are.
+    pub fn is_occupied(&self, seat: Seat) -> bool {
+        self.plane.contains(seat) && self.occupied[self.plane.id(seat)].is_some()
+    }
+
+    /// Every empty seat, in id order, along with where it sits.
+    pub fn empty_seats(&self) -> Vec<(Seat, Gap)> {
+        let first = self.occupied.iter().position(Option::is_some);
+        let last = self.occupied.iter().rposition(Option::is_some);
+        let mut empty = vec![];
+        for (id, line) in self.occupied.iter().enumerate() {
+            if line.is_some() {
+                continue;
+            }
+            let gap = match (first, last) {
+                (Some(first), _) if id < first => Gap::Front,
+                (_, Some(last)) if id < last => Gap::Interior,
+                _ => Gap::Back,
+            };
+            empty.push((self.plane.seat(id), gap));
+        }
+        empty
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = (self.plane.rows() - 1).to_string().len();
+        for (row, seats) in self.occupied.chunks(self.plane.columns()).enumerate() {
+            if row != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{:>width$} ", row, width = width)?;
+            for seat in seats {
+                write!(f, "{}", if seat.is_some() { '#' } else { '.' })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes every empty seat with where it sits. Whole rows missing at the
+/// front or back are written as ranges of rows rather than seat by seat.
+fn write_empty_seats<W: Write>(out: &mut W, plane: Plane, empty: &[(Seat, Gap)]) -> io::Result<()> {
+    let columns = plane.columns();
+    let mut i = 0;
+    while i < empty.len() {
+        let (seat, gap) = empty[i];
+        let mut rows = 0;
+        if gap != Gap::Interior && seat.column == 0 {
+            // Ids only go up, so a row's last seat `columns` on means it's all empty
+            while let Some(&(last, g)) = empty.get(i + (rows + 1) * columns - 1) {
+                if g != gap || last.row != seat.row + rows || last.column != columns - 1 {
+                    break;
+                }
+                rows += 1;
+            }
+        }
+        match rows {
+            0 if gap == Gap::Interior => writeln!(
+                out,
+                "Seat: {} (row {}, column {})",
+                plane.id(seat),
+                seat.row,
+                seat.column
+            )?,
+            0 => writeln!(
+                out,
+                "{:?}: seat {} (row {}, column {})",
+                gap,
+                plane.id(seat),
+                seat.row,
+                seat.column
+            )?,
+            1 => writeln!(out, "{:?}: row {}", gap, seat.row)?,
+            _ => writeln!(out, "{:?}: rows {}-{}", gap, seat.row, seat.row + rows - 1)?,
+        }
+        i += if rows == 0 { 1 } else { rows * columns };
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut input_path = "input.txt".to_string();
+    let mut show_map = false;
+    let (mut rows, mut columns) = (Plane::default().rows(), Plane::default().columns());
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--map" => show_map = true,
+            "--rows" => rows = args.next().ok_or("--rows needs a number")?.parse()?,
+            "--columns" => columns = args.next().ok_or("--columns needs a number")?.parse()?,
+            _ => input_path = arg,
+        }
+    }
+
+    let plane = Plane::with_size(rows, columns)?;
+    let input = std::fs::read_to_string(input_path)?;
+    let manifest = Manifest::new(plane, &input);
+    for (line, e) in &manifest.invalid {
+        eprintln!("line {}: invalid boarding pass: {}", line, e);
+    }
+    for (line, first_line, seat) in &manifest.duplicates {
+        eprintln!(
+            "line {}: seat {} (row {}, column {}) already taken on line {}",
+            line,
+            plane.id(*seat),
+            seat.row,
+            seat.column,
+            first_line
+        );
+    }
+
+    let empty = manifest.empty_seats();
+    if show_map {
+        println!("{}", manifest);
+        let count = |gap| empty.iter().filter(|(_, g)| *g == gap).count();
+        println!("Empty at front: {}", count(Gap::Front));
+        println!("Empty at back: {}", count(Gap::Back));
     }
+    let stdout = io::stdout();
+    write_empty_seats(&mut stdout.lock(), plane, &empty)?;
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::decode;
+    use super::*;
 
     #[test]
     fn test_decode_seat_id() {
         let seat = "FBFBBFFRLR";
         let decoded = decode(seat);
-        assert_eq!(decoded, 357);
+        assert_eq!(decoded, Ok(357));
+    }
+
+    #[test]
+    fn test_decode_seat() {
+        let plane = Plane::default();
+        assert_eq!(plane.decode("BFFFBBFRRR"), Ok(Seat { row: 70, column: 7 }));
+        assert_eq!(plane.decode("FFFBBBFRRR"), Ok(Seat { row: 14, column: 7 }));
+        assert_eq!(
+            plane.decode("BBFFBBFRLL"),
+            Ok(Seat {
+                row: 102,
+                column: 4
+            })
+        );
+        assert_eq!(plane.decode_id("BBFFBBFRLL"), Ok(820));
+    }
+
+    #[test]
+    fn test_encode() {
+        let plane = Plane::default();
+        assert_eq!(
+            plane.encode(Seat { row: 44, column: 5 }).as_deref(),
+            Some("FBFBBFFRLR")
+        );
+        assert_eq!(plane.encode_id(567).as_deref(), Some("BFFFBBFRRR"));
+        assert_eq!(
+            Plane::new(2, 1).unwrap().encode_id(5).as_deref(),
+            Some("BFR")
+        );
+        assert_eq!(plane.encode_id(1024), None);
+        assert_eq!(plane.encode(Seat { row: 3, column: 8 }), None);
+        assert_eq!(
+            plane.encode(Seat {
+                row: 128,
+                column: 0
+            }),
+            None
+        );
+    }
+
+    #[test]
+    fn test_plane_size() {
+        assert_eq!(Plane::with_size(128, 8), Ok(Plane::default()));
+        assert_eq!(Plane::with_size(1, 1), Plane::new(0, 0));
+        assert_eq!(
+            Plane::with_size(100, 8),
+            Err(PlaneError::NotPowerOfTwo(100))
+        );
+        assert_eq!(Plane::with_size(0, 8), Err(PlaneError::NotPowerOfTwo(0)));
+        assert_eq!(Plane::new(12, 9), Err(PlaneError::TooLarge { bits: 21 }));
+        assert_eq!(
+            Plane::new(64, u32::MAX),
+            Err(PlaneError::TooLarge { bits: u32::MAX })
+        );
+        assert_eq!(Plane::new(16, 4).map(|plane| plane.seats()), Ok(1 << 20));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for &plane in &[
+            Plane::default(),
+            Plane::new(1, 1).unwrap(),
+            Plane::new(4, 2).unwrap(),
+            Plane::new(3, 0).unwrap(),
+            Plane::new(8, 4).unwrap(),
+        ] {
+            for id in 0..plane.seats() {
+                let pass = plane.encode_id(id).unwrap();
+                assert_eq!(pass.len() as u32, plane.row_bits + plane.column_bits);
+                assert_eq!(plane.decode_id(&pass), Ok(id), "{:?} {}", plane, pass);
+                let seat = plane.seat(id);
+                assert_eq!(plane.decode(&plane.encode(seat).unwrap()), Ok(seat));
+                assert_eq!(plane.id(seat), id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert_eq!(
+            decode("FBFBBFFRL"),
+            Err(PassError::Length {
+                expected: 10,
+                found: 9
+            })
+        );
+        assert_eq!(
+            decode("FBFXBFFRLR"),
+            Err(PassError::Character {
+                position: 3,
+                found: 'X'
+            })
+        );
+        assert_eq!(
+            decode("FBFBBFFRLB"),
+            Err(PassError::Character {
+                position: 9,
+                found: 'B'
+            })
+        );
+        assert_eq!(
+            decode("FBFBBFéRLR"),
+            Err(PassError::Character {
+                position: 6,
+                found: 'é'
+            })
+        );
+    }
+
+    #[test]
+    fn test_manifest() {
+        let plane = Plane::new(2, 1).unwrap();
+        let input = "FBL\nFBR\nBFR\nFBL\nFBX\nBFL";
+        let manifest = Manifest::new(plane, input);
+        assert_eq!(
+            manifest.duplicates,
+            vec![(4, 1, Seat { row: 1, column: 0 })]
+        );
+        assert_eq!(manifest.invalid.len(), 1);
+        assert_eq!(manifest.invalid[0].0, 5);
+        assert_eq!(manifest.to_string(), "0 ..\n1 ##\n2 ##\n3 ..");
+        assert_eq!(
+            manifest.empty_seats(),
+            vec![
+                (Seat { row: 0, column: 0 }, Gap::Front),
+                (Seat { row: 0, column: 1 }, Gap::Front),
+                (Seat { row: 3, column: 0 }, Gap::Back),
+                (Seat { row: 3, column: 1 }, Gap::Back),
+            ]
+        );
+
+        let manifest = Manifest::new(plane, "FFR\nFBR\nBBL");
+        assert!(manifest.is_occupied(Seat { row: 0, column: 1 }));
+        // Row 0, column 3 would read row 1, column 1 if it weren't checked
+        assert!(manifest.is_occupied(Seat { row: 1, column: 1 }));
+        assert!(!manifest.is_occupied(Seat { row: 0, column: 3 }));
+        assert!(!manifest.is_occupied(Seat { row: 9, column: 0 }));
+        let interior: Vec<_> = manifest
+            .empty_seats()
+            .into_iter()
+            .filter(|(_, gap)| *gap == Gap::Interior)
+            .map(|(seat, _)| plane.id(seat))
+            .collect();
+        assert_eq!(interior, vec![2, 4, 5]);
+    }
+
+    #[test]
+    fn test_write_empty_seats() {
+        let write = |plane: Plane, input: &str| {
+            let mut out = vec![];
+            let empty = Manifest::new(plane, input).empty_seats();
+            write_empty_seats(&mut out, plane, &empty).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let plane = Plane::new(3, 1).unwrap();
+        assert_eq!(
+            write(plane, "FBFR\nBFFL\nBFBL"),
+            "Front: rows 0-1\n\
+             Front: seat 4 (row 2, column 0)\n\
+             Seat: 6 (row 3, column 0)\n\
+             Seat: 7 (row 3, column 1)\n\
+             Seat: 9 (row 4, column 1)\n\
+             Back: seat 11 (row 5, column 1)\n\
+             Back: rows 6-7\n"
+        );
+        assert_eq!(
+            write(plane, "FFFL\nBBBR"),
+            "Seat: 1 (row 0, column 1)\n\
+             Seat: 2 (row 1, column 0)\n\
+             Seat: 3 (row 1, column 1)\n\
+             Seat: 4 (row 2, column 0)\n\
+             Seat: 5 (row 2, column 1)\n\
+             Seat: 6 (row 3, column 0)\n\
+             Seat: 7 (row 3, column 1)\n\
+             Seat: 8 (row 4, column 0)\n\
+             Seat: 9 (row 4, column 1)\n\
+             Seat: 10 (row 5, column 0)\n\
+             Seat: 11 (row 5, column 1)\n\
+             Seat: 12 (row 6, column 0)\n\
+             Seat: 13 (row 6, column 1)\n\
+             Seat: 14 (row 7, column 0)\n"
+        );
+        assert_eq!(write(plane, ""), "Back: rows 0-7\n");
+        assert_eq!(
+            write(Plane::new(1, 1).unwrap(), "BL"),
+            "Front: row 0\nBack: seat 3 (row 1, column 1)\n"
+        );
     }
 }
@@ -1,33 +1,448 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
 
-pub fn decode_group_union(group: &str) -> usize {
-    let mut all = BTreeSet::new();
-    for line in group.lines() {
-        for c in line.chars() {
-            all.insert(c);
+/// The answers of everyone in one customs group, one set per person.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Group {
+    people: Vec<BTreeSet<char>>,
+}
+
+impl From<&str> for Group {
+    fn from(group: &str) -> Self {
+        Group {
+            people: group
+                .lines()
+                .map(|line| line.chars().collect::<BTreeSet<char>>())
+                .collect(),
         }
     }
-    all.len()
+}
+
+impl Group {
+    pub fn size(&self) -> usize {
+        self.people.len()
+    }
+
+    /// How many people answered yes to each question.
+    pub fn histogram(&self) -> BTreeMap<char, usize> {
+        let mut counts = BTreeMap::new();
+        for person in &self.people {
+            for &c in person {
+                *counts.entry(c).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    fn answered_by(&self, pred: impl Fn(usize) -> bool) -> BTreeSet<char> {
+        self.histogram()
+            .into_iter()
+            .filter(|&(_, n)| pred(n))
+            .map(|(c, _)| c)
+            .collect()
+    }
+
+    pub fn union(&self) -> BTreeSet<char> {
+        self.answered_by(|n| n > 0)
+    }
+
+    pub fn intersection(&self) -> BTreeSet<char> {
+        let mut sets = self.people.iter();
+        if let Some(first) = sets.next() {
+            let mut all = first.clone();
+            for set in sets {
+                all = all.intersection(set).cloned().collect()
+            }
+            all
+        } else {
+            BTreeSet::new()
+        }
+    }
+
+    pub fn at_least(&self, k: usize) -> BTreeSet<char> {
+        self.answered_by(|n| n >= k)
+    }
+
+    pub fn exactly(&self, k: usize) -> BTreeSet<char> {
+        self.answered_by(|n| n == k)
+    }
+
+    /// The symmetric difference of everyone's answers, i.e. the questions an
+    /// odd number of people answered.
+    pub fn symmetric_difference(&self) -> BTreeSet<char> {
+        self.people.iter().fold(BTreeSet::new(), |acc, set| {
+            acc.symmetric_difference(set).cloned().collect()
+        })
+    }
+
+    pub fn query(&self, query: &Query) -> Answer {
+        match query {
+            Query::Set(expr) => Answer::Set(expr.eval(self)),
+            Query::Histogram => Answer::Histogram(self.histogram()),
+        }
+    }
+}
+
+pub fn decode_group_union(group: &str) -> usize {
+    Group::from(group).union().len()
 }
 
 pub fn decode_group(group: &str) -> usize {
-    let mut sets = group
-        .lines()
-        .map(|line| line.chars().collect::<BTreeSet<char>>());
-    if let Some(mut all) = sets.next() {
-        for set in sets {
-            all = all.intersection(&set).cloned().collect()
+    Group::from(group).intersection().len()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetOp {
+    Union,
+    Intersection,
+    Difference,
+    SymmetricDifference,
+}
+
+/// A set-valued query over a group's answers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Expr {
+    Any,
+    All,
+    AtLeast(usize),
+    Exactly(usize),
+    Odd,
+    Op(Box<Expr>, SetOp, Box<Expr>),
+}
+
+impl Expr {
+    pub fn eval(&self, group: &Group) -> BTreeSet<char> {
+        match self {
+            Expr::Any => group.union(),
+            Expr::All => group.intersection(),
+            Expr::AtLeast(k) => group.at_least(*k),
+            Expr::Exactly(k) => group.exactly(*k),
+            Expr::Odd => group.symmetric_difference(),
+            Expr::Op(left, op, right) => {
+                let (left, right) = (left.eval(group), right.eval(group));
+                match op {
+                    SetOp::Union => left.union(&right).cloned().collect(),
+                    SetOp::Intersection => left.intersection(&right).cloned().collect(),
+                    SetOp::Difference => left.difference(&right).cloned().collect(),
+                    SetOp::SymmetricDifference => {
+                        left.symmetric_difference(&right).cloned().collect()
+                    }
+                }
+            }
         }
-        all.len()
-    } else {
-        0
     }
 }
 
-fn main() {
-    let input = std::fs::read_to_string("input.txt").unwrap();
-    let groups: Vec<_> = input.trim().split("\n\n").map(decode_group).collect();
-    println!("Sum: {}", groups.iter().sum::<usize>());
+/// A query from the command line:
+///
+/// ```text
+/// query   = "histogram" | expr
+/// expr    = term { ("|" | "&" | "-" | "^") term }
+/// term    = "any" | "all" | "odd" | "atleast" N | "exactly" N | "(" expr ")"
+/// ```
+///
+/// Operators are union, intersection, difference and symmetric difference,
+/// and apply left to right.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Query {
+    Set(Expr),
+    Histogram,
+}
+
+struct Parser<'a> {
+    tokens: std::iter::Peekable<std::vec::IntoIter<&'a str>>,
+}
+
+impl<'a> Parser<'a> {
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.term()?;
+        while let Some(&token) = self.tokens.peek() {
+            let op = match token {
+                "|" => SetOp::Union,
+                "&" => SetOp::Intersection,
+                "-" => SetOp::Difference,
+                "^" => SetOp::SymmetricDifference,
+                _ => break,
+            };
+            self.tokens.next();
+            left = Expr::Op(Box::new(left), op, Box::new(self.term()?));
+        }
+        Ok(left)
+    }
+
+    fn number(&mut self, name: &str) -> Result<usize, String> {
+        let token = self
+            .tokens
+            .next()
+            .ok_or_else(|| format!("{} needs a number", name))?;
+        token
+            .parse()
+            .map_err(|_| format!("{} needs a number, found {:?}", name, token))
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        match self.tokens.next() {
+            Some("any") => Ok(Expr::Any),
+            Some("all") => Ok(Expr::All),
+            Some("odd") => Ok(Expr::Odd),
+            Some("atleast") => Ok(Expr::AtLeast(self.number("atleast")?)),
+            Some("exactly") => Ok(Expr::Exactly(self.number("exactly")?)),
+            Some("(") => {
+                let expr = self.expr()?;
+                match self.tokens.next() {
+                    Some(")") => Ok(expr),
+                    _ => Err("missing )".to_string()),
+                }
+            }
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end of query".to_string()),
+        }
+    }
+}
+
+impl FromStr for Query {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "histogram" {
+            return Ok(Query::Histogram);
+        }
+        let spaced: String = s
+            .chars()
+            .flat_map(|c| match c {
+                '(' | ')' | '|' | '&' | '-' | '^' => vec![' ', c, ' '],
+                _ => vec![c],
+            })
+            .collect();
+        let tokens: Vec<&str> = spaced.split_whitespace().collect();
+        let mut parser = Parser {
+            tokens: tokens.into_iter().peekable(),
+        };
+        let expr = parser.expr()?;
+        match parser.tokens.next() {
+            None => Ok(Query::Set(expr)),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+}
+
+/// The result of a query: the matching questions, or per-question counts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Answer {
+    Set(BTreeSet<char>),
+    Histogram(BTreeMap<char, usize>),
+}
+
+impl Answer {
+    pub fn count(&self) -> usize {
+        match self {
+            Answer::Set(set) => set.len(),
+            Answer::Histogram(counts) => counts.values().sum(),
+        }
+    }
+}
+
+impl fmt::Display for Answer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Answer::Set(set) => {
+                write!(f, "{}", set.len())?;
+                if !set.is_empty() {
+                    write!(f, " ")?;
+                }
+                for c in set {
+                    write!(f, "{}", c)?;
+                }
+                Ok(())
+            }
+            Answer::Histogram(counts) => {
+                let pairs: Vec<_> = counts.iter().map(|(c, n)| format!("{}:{}", c, n)).collect();
+                write!(f, "{}", pairs.join(" "))
+            }
+        }
+    }
+}
+
+fn letters(set: &BTreeSet<char>) -> String {
+    set.iter().collect()
+}
+
+/// A summary of one group's answers. The most and least common questions
+/// include every question tied for first or last place.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupStats {
+    pub size: usize,
+    pub union: BTreeSet<char>,
+    pub intersection: BTreeSet<char>,
+    pub most_common: BTreeSet<char>,
+    pub least_common: BTreeSet<char>,
+}
+
+impl Group {
+    pub fn stats(&self) -> GroupStats {
+        let histogram = self.histogram();
+        let max = histogram.values().max().copied();
+        let min = histogram.values().min().copied();
+        let with_count = |count| {
+            histogram
+                .iter()
+                .filter(|&(_, &n)| Some(n) == count)
+                .map(|(&c, _)| c)
+                .collect()
+        };
+        GroupStats {
+            size: self.size(),
+            union: self.union(),
+            intersection: self.intersection(),
+            most_common: with_count(max),
+            least_common: with_count(min),
+        }
+    }
+}
+
+/// How often each question was answered across every group.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Frequency {
+    pub people: usize,
+    pub groups: usize,
+    pub unanimous: usize,
+}
+
+pub fn frequencies(groups: &[Group]) -> BTreeMap<char, Frequency> {
+    let mut frequencies: BTreeMap<char, Frequency> = BTreeMap::new();
+    for group in groups {
+        for (c, n) in group.histogram() {
+            let frequency = frequencies.entry(c).or_default();
+            frequency.people += n;
+            frequency.groups += 1;
+            if n == group.size() {
+                frequency.unanimous += 1;
+            }
+        }
+    }
+    frequencies
+}
+
+pub fn write_groups_csv<W: Write>(out: &mut W, groups: &[Group]) -> io::Result<()> {
+    writeln!(
+        out,
+        "group,size,union,intersection,most_common,least_common"
+    )?;
+    for (i, group) in groups.iter().enumerate() {
+        let stats = group.stats();
+        writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            i + 1,
+            stats.size,
+            letters(&stats.union),
+            letters(&stats.intersection),
+            letters(&stats.most_common),
+            letters(&stats.least_common)
+        )?;
+    }
+    Ok(())
+}
+
+pub fn write_questions_csv<W: Write>(out: &mut W, groups: &[Group]) -> io::Result<()> {
+    writeln!(out, "question,people,groups,unanimous")?;
+    for (c, frequency) in frequencies(groups) {
+        writeln!(
+            out,
+            "{},{},{},{}",
+            c, frequency.people, frequency.groups, frequency.unanimous
+        )?;
+    }
+    Ok(())
+}
+
+pub fn write_report<W: Write>(out: &mut W, groups: &[Group]) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:>5} {:>4}  {:<26} {:<26} {:<26} least",
+        "group", "size", "union", "intersection", "most"
+    )?;
+    for (i, group) in groups.iter().enumerate() {
+        let stats = group.stats();
+        writeln!(
+            out,
+            "{:>5} {:>4}  {:<26} {:<26} {:<26} {}",
+            i + 1,
+            stats.size,
+            letters(&stats.union),
+            letters(&stats.intersection),
+            letters(&stats.most_common),
+            letters(&stats.least_common)
+        )?;
+    }
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{:>8} {:>6} {:>6} {:>9}",
+        "question", "people", "groups", "unanimous"
+    )?;
+    for (c, frequency) in frequencies(groups) {
+        writeln!(
+            out,
+            "{:>8} {:>6} {:>6} {:>9}",
+            c, frequency.people, frequency.groups, frequency.unanimous
+        )?;
+    }
+    Ok(())
+}
+
+enum Mode {
+    Sum,
+    Query(Query),
+    Report,
+    GroupsCsv,
+    QuestionsCsv,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut input_path = "input.txt".to_string();
+    let mut mode = Mode::Sum;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--query" => mode = Mode::Query(args.next().ok_or("--query needs a query")?.parse()?),
+            "--report" => mode = Mode::Report,
+            "--csv" => {
+                mode = match args.next().as_deref() {
+                    Some("groups") => Mode::GroupsCsv,
+                    Some("questions") => Mode::QuestionsCsv,
+                    _ => return Err("--csv needs groups or questions".into()),
+                }
+            }
+            _ => input_path = arg,
+        }
+    }
+
+    let input = std::fs::read_to_string(input_path)?;
+    let groups: Vec<_> = input.trim().split("\n\n").map(Group::from).collect();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match mode {
+        Mode::Sum => {
+            let sum: usize = groups.iter().map(|g| g.intersection().len()).sum();
+            println!("Sum: {}", sum);
+        }
+        Mode::Query(query) => {
+            let mut total = 0;
+            for (i, group) in groups.iter().enumerate() {
+                let answer = group.query(&query);
+                total += answer.count();
+                println!("#{}: {}", i + 1, answer);
+            }
+            println!("Sum: {}", total);
+        }
+        Mode::Report => write_report(&mut out, &groups)?,
+        Mode::GroupsCsv => write_groups_csv(&mut out, &groups)?,
+        Mode::QuestionsCsv => write_questions_csv(&mut out, &groups)?,
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -49,4 +464,100 @@ mod tests {
         assert_eq!(decode_group("a\nb\nc"), 0);
         assert_eq!(decode_group("a\nabc"), 1);
     }
+
+    fn set(s: &str) -> Answer {
+        Answer::Set(s.chars().collect())
+    }
+
+    fn query(group: &str, query: &str) -> Answer {
+        Group::from(group).query(&query.parse().unwrap())
+    }
+
+    #[test]
+    fn test_query() {
+        let group = "ab\nac\nad\nb";
+        assert_eq!(query(group, "any"), set("abcd"));
+        assert_eq!(query(group, "all"), set(""));
+        assert_eq!(query(group, "atleast 2"), set("ab"));
+        assert_eq!(query(group, "exactly 1"), set("cd"));
+        assert_eq!(query(group, "odd"), set("acd"));
+        assert_eq!(query(group, "any - atleast 2"), set("cd"));
+        assert_eq!(query(group, "atleast 3 | exactly 2"), set("ab"));
+        assert_eq!(query(group, "any & (odd ^ atleast 2)"), set("bcd"));
+        assert_eq!(query(group, "odd^any"), set("b"));
+
+        let histogram = query(group, "histogram");
+        assert_eq!(histogram.count(), 7);
+        assert_eq!(histogram.to_string(), "a:3 b:2 c:1 d:1");
+        assert_eq!(query(group, "atleast 2").to_string(), "2 ab");
+        assert_eq!(query(group, "all").to_string(), "0");
+    }
+
+    #[test]
+    fn test_query_errors() {
+        assert!("".parse::<Query>().is_err());
+        assert!("atleast".parse::<Query>().is_err());
+        assert!("atleast x".parse::<Query>().is_err());
+        assert!("(any".parse::<Query>().is_err());
+        assert!("any all".parse::<Query>().is_err());
+        assert!("some".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn test_stats() {
+        let stats = Group::from("ab\nac\nad\nb").stats();
+        assert_eq!(
+            stats,
+            GroupStats {
+                size: 4,
+                union: "abcd".chars().collect(),
+                intersection: BTreeSet::new(),
+                most_common: "a".chars().collect(),
+                least_common: "cd".chars().collect(),
+            }
+        );
+
+        let stats = Group::from("").stats();
+        assert_eq!(stats.size, 0);
+        assert!(stats.most_common.is_empty());
+    }
+
+    #[test]
+    fn test_frequencies() {
+        let groups: Vec<_> = ["abc", "a\nb\nc", "ab\nac", "a\na\na\na", "b"]
+            .iter()
+            .map(|g| Group::from(*g))
+            .collect();
+        let frequencies = frequencies(&groups);
+        assert_eq!(
+            frequencies[&'a'],
+            Frequency {
+                people: 8,
+                groups: 4,
+                unanimous: 3
+            }
+        );
+        assert_eq!(
+            frequencies[&'c'],
+            Frequency {
+                people: 3,
+                groups: 3,
+                unanimous: 1
+            }
+        );
+
+        let mut csv = vec![];
+        write_questions_csv(&mut csv, &groups[..2]).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "question,people,groups,unanimous\na,2,2,1\nb,2,2,1\nc,2,2,1\n"
+        );
+
+        let mut csv = vec![];
+        write_groups_csv(&mut csv, &groups[2..3]).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "group,size,union,intersection,most_common,least_common\n1,2,abc,a,a,bc\n"
+        );
+    }
 }
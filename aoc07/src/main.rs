@@ -1,4 +1,8 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
 
 #[macro_use]
 extern crate lazy_static;
@@ -16,91 +20,619 @@ struct Rule {
     contents: Vec<Content>,
 }
 
-impl Rule {
-    pub fn color_count(&self, color: &str) -> Option<usize> {
-        if let Some(content) = self.contents.iter().find(|c| c.color == color) {
-            return Some(content.count);
+type RuleSet = HashMap<String, Rule>;
+
+/// The rules as an indexed graph, with containment edges in both directions.
+/// Query results are cached per colour. Bag counts build on the counts of the
+/// bags inside, so every count together costs one walk of the graph; the
+/// containers of each colour still take a walk of their own.
+#[derive(Debug)]
+struct BagGraph {
+    colors: Vec<String>,
+    index: HashMap<String, usize>,
+    /// `(child, count)` for each bag directly inside a bag.
+    children: Vec<Vec<(usize, usize)>>,
+    /// Every bag that directly holds a bag.
+    parents: Vec<Vec<usize>>,
+    /// `None` until worked out, then `Some(None)` if the count overflowed.
+    inside: RefCell<Vec<Option<Option<usize>>>>,
+    containers: RefCell<HashMap<usize, Rc<BTreeSet<usize>>>>,
+}
+
+impl BagGraph {
+    fn new(ruleset: &RuleSet) -> Self {
+        let mut graph = BagGraph {
+            colors: vec![],
+            index: HashMap::new(),
+            children: vec![],
+            parents: vec![],
+            inside: RefCell::new(vec![]),
+            containers: RefCell::new(HashMap::new()),
+        };
+        let mut colors: Vec<_> = ruleset.keys().collect();
+        colors.sort();
+        for color in colors {
+            let rule = &ruleset[color];
+            let parent = graph.add_color(&rule.color);
+            for content in &rule.contents {
+                let child = graph.add_color(&content.color);
+                graph.children[parent].push((child, content.count));
+                graph.parents[child].push(parent);
+            }
         }
-        None
+        graph.inside = RefCell::new(vec![None; graph.colors.len()]);
+        graph
+    }
+
+    fn add_color(&mut self, color: &str) -> usize {
+        if let Some(&id) = self.index.get(color) {
+            return id;
+        }
+        let id = self.colors.len();
+        self.colors.push(color.to_string());
+        self.index.insert(color.to_string(), id);
+        self.children.push(vec![]);
+        self.parents.push(vec![]);
+        id
+    }
+
+    fn id(&self, color: &str) -> Option<usize> {
+        self.index.get(color).copied()
+    }
+
+    fn color(&self, id: usize) -> &str {
+        &self.colors[id]
+    }
+
+    fn containers_of(&self, id: usize) -> Rc<BTreeSet<usize>> {
+        if let Some(found) = self.containers.borrow().get(&id) {
+            return Rc::clone(found);
+        }
+        let mut found = BTreeSet::new();
+        let mut stack = vec![id];
+        while let Some(next) = stack.pop() {
+            for &parent in &self.parents[next] {
+                if found.insert(parent) {
+                    stack.push(parent);
+                }
+            }
+        }
+        let found = Rc::new(found);
+        self.containers.borrow_mut().insert(id, Rc::clone(&found));
+        found
+    }
+
+    /// Every colour that can eventually hold a bag of `color`, sorted.
+    fn containers(&self, color: &str) -> Option<Vec<&str>> {
+        let id = self.id(color)?;
+        let mut colors: Vec<_> = self
+            .containers_of(id)
+            .iter()
+            .map(|&c| self.color(c))
+            .collect();
+        colors.sort_unstable();
+        Some(colors)
+    }
+
+    fn inside_of(&self, id: usize) -> Option<usize> {
+        if let Some(total) = self.inside.borrow()[id] {
+            return total;
+        }
+        let mut total = Some(0usize);
+        for &(child, count) in &self.children[id] {
+            let bags = self
+                .inside_of(child)
+                .and_then(|inside| inside.checked_add(1))
+                .and_then(|each| each.checked_mul(count));
+            total = total
+                .zip(bags)
+                .and_then(|(total, bags)| total.checked_add(bags));
+        }
+        self.inside.borrow_mut()[id] = Some(total);
+        total
+    }
+
+    /// The total number of bags inside one bag of `color`, or `None` if there
+    /// are too many to count.
+    fn bags_inside(&self, color: &str) -> Option<usize> {
+        self.inside_of(self.id(color)?)
     }
 }
 
-type RuleSet = HashMap<String, Rule>;
+impl BagGraph {
+    /// The fewest nestings that get from a bag of `outer` to one of `inner`,
+    /// as the colours along the way.
+    fn shortest_chain(&self, outer: &str, inner: &str) -> Option<Vec<&str>> {
+        let (outer, inner) = (self.id(outer)?, self.id(inner)?);
+        let mut previous: HashMap<usize, usize> = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(outer);
+        while let Some(node) = queue.pop_front() {
+            if node == inner && node != outer {
+                break;
+            }
+            for &(child, _) in &self.children[node] {
+                if child != outer && !previous.contains_key(&child) {
+                    previous.insert(child, node);
+                    queue.push_back(child);
+                }
+            }
+        }
+        let mut chain = vec![self.color(inner)];
+        let mut node = *previous.get(&inner)?;
+        while node != outer {
+            chain.push(self.color(node));
+            node = previous[&node];
+        }
+        chain.push(self.color(outer));
+        chain.reverse();
+        Some(chain)
+    }
 
-fn traverse_to(ruleset: &RuleSet, start: &str, needle: &str) -> Option<usize> {
-    let rule = ruleset.get(start)?;
-    if let Some(v) = rule.color_count(needle) {
-        return Some(v);
-    }
-    let counts = rule
-        .contents
-        .iter()
-        .filter_map(|content| traverse_to(ruleset, &content.color, needle))
-        .collect::<Vec<_>>();
-    if counts.is_empty() {
-        None
-    } else {
-        Some(counts.iter().sum())
+    fn longest_from(
+        &self,
+        node: usize,
+        inner: usize,
+        reaches: &BTreeSet<usize>,
+        memo: &mut HashMap<usize, Option<usize>>,
+    ) -> Option<usize> {
+        if let Some(&found) = memo.get(&node) {
+            return found;
+        }
+        let mut best = None;
+        for &(child, _) in &self.children[node] {
+            let length = if child == inner {
+                Some(1)
+            } else if reaches.contains(&child) {
+                self.longest_from(child, inner, reaches, memo)
+                    .map(|n| n + 1)
+            } else {
+                None
+            };
+            best = best.max(length);
+        }
+        memo.insert(node, best);
+        best
+    }
+
+    /// The most nestings that get from a bag of `outer` to one of `inner`.
+    /// Rule sets from `parse_rules` have no cycles, so this always ends.
+    fn longest_chain(&self, outer: &str, inner: &str) -> Option<Vec<&str>> {
+        let (outer, inner) = (self.id(outer)?, self.id(inner)?);
+        let reaches = self.containers_of(inner);
+        let mut memo = HashMap::new();
+        self.longest_from(outer, inner, &reaches, &mut memo)?;
+
+        let mut chain = vec![self.color(outer)];
+        let mut node = outer;
+        while node != inner {
+            let remaining = memo[&node];
+            node = self.children[node]
+                .iter()
+                .map(|&(child, _)| child)
+                .find(|&child| {
+                    if child == inner {
+                        remaining == Some(1)
+                    } else {
+                        memo.get(&child).copied().flatten().map(|n| n + 1) == remaining
+                    }
+                })?;
+            chain.push(self.color(node));
+        }
+        Some(chain)
+    }
+
+    fn write_subtree<W: Write>(
+        &self,
+        out: &mut W,
+        id: usize,
+        multiplier: usize,
+        indent: usize,
+    ) -> io::Result<()> {
+        for &(child, count) in &self.children[id] {
+            let total = multiplier.checked_mul(count).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "too many bags to count")
+            })?;
+            writeln!(
+                out,
+                "{:indent$}{} {} ({} total)",
+                "",
+                count,
+                self.color(child),
+                total,
+                indent = indent * 2
+            )?;
+            self.write_subtree(out, child, total, indent + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Everything inside a bag of `color`, one bag per line, with how many
+    /// are in their parent and how many there are overall.
+    fn write_tree<W: Write>(&self, out: &mut W, color: &str) -> io::Result<()> {
+        let id = self
+            .id(color)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no {} bags", color)))?;
+        writeln!(out, "{}", color)?;
+        self.write_subtree(out, id, 1, 1)
     }
 }
 
-fn count_below(ruleset: &RuleSet, start: &Rule, multiplier: usize) -> usize {
-    let mut sum = 0;
-    for content in start.contents.iter() {
-        let rule = &ruleset[&content.color];
-        sum +=
-            (multiplier * content.count) + count_below(ruleset, rule, multiplier * content.count);
+/// What to include in, and emphasise on, a Graphviz rendering of the rules.
+#[derive(Debug, Default)]
+struct DotOptions {
+    /// Colour every bag that can hold this one, and every bag it holds.
+    highlight: Option<String>,
+    /// Only draw bags within `depth` rules of this one, in either direction.
+    around: Option<String>,
+    depth: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Target,
+    Container,
+    Content,
+    Plain,
+}
+
+impl BagGraph {
+    /// Every colour that can eventually be found inside a bag.
+    fn contents_of(&self, id: usize) -> BTreeSet<usize> {
+        let mut found = BTreeSet::new();
+        let mut stack = vec![id];
+        while let Some(next) = stack.pop() {
+            for &(child, _) in &self.children[next] {
+                if found.insert(child) {
+                    stack.push(child);
+                }
+            }
+        }
+        found
+    }
+
+    /// Every colour at most `depth` rules away from `id`, following
+    /// containment both ways.
+    fn neighbourhood(&self, id: usize, depth: usize) -> BTreeSet<usize> {
+        let mut found: BTreeSet<usize> = vec![id].into_iter().collect();
+        let mut frontier = vec![id];
+        for _ in 0..depth {
+            let mut next = vec![];
+            for &node in &frontier {
+                let neighbours = self.children[node]
+                    .iter()
+                    .map(|&(child, _)| child)
+                    .chain(self.parents[node].iter().copied());
+                for neighbour in neighbours {
+                    if found.insert(neighbour) {
+                        next.push(neighbour);
+                    }
+                }
+            }
+            frontier = next;
+        }
+        found
+    }
+
+    fn lookup(&self, color: &Option<String>) -> io::Result<Option<usize>> {
+        match color {
+            Some(color) => self.id(color).map(Some).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, format!("no {} bags", color))
+            }),
+            None => Ok(None),
+        }
+    }
+
+    fn write_dot<W: Write>(&self, out: &mut W, options: &DotOptions) -> io::Result<()> {
+        let included: BTreeSet<usize> = match self.lookup(&options.around)? {
+            Some(id) => self.neighbourhood(id, options.depth),
+            None => (0..self.colors.len()).collect(),
+        };
+        let mut roles = vec![Role::Plain; self.colors.len()];
+        if let Some(id) = self.lookup(&options.highlight)? {
+            for &container in self.containers_of(id).iter() {
+                roles[container] = Role::Container;
+            }
+            for content in self.contents_of(id) {
+                roles[content] = Role::Content;
+            }
+            roles[id] = Role::Target;
+        }
+
+        let mut nodes: Vec<_> = included.iter().copied().collect();
+        nodes.sort_by_key(|&id| self.color(id));
+
+        writeln!(out, "digraph bags {{")?;
+        for &id in &nodes {
+            let style = match roles[id] {
+                Role::Target => " [style=filled, fillcolor=gold]",
+                Role::Container => " [style=filled, fillcolor=lightblue]",
+                Role::Content => " [style=filled, fillcolor=palegreen]",
+                Role::Plain => "",
+            };
+            writeln!(out, "    {:?}{};", self.color(id), style)?;
+        }
+        for &id in &nodes {
+            let mut children: Vec<_> = self.children[id]
+                .iter()
+                .filter(|(child, _)| included.contains(child))
+                .collect();
+            children.sort_by_key(|&&(child, _)| self.color(child));
+            for &(child, count) in children {
+                let style = match (roles[id], roles[child]) {
+                    (Role::Container, Role::Container) | (Role::Container, Role::Target) => {
+                        ", color=blue"
+                    }
+                    (Role::Target, Role::Content) | (Role::Content, Role::Content) => {
+                        ", color=darkgreen"
+                    }
+                    _ => "",
+                };
+                writeln!(
+                    out,
+                    "    {:?} -> {:?} [label=\"{}\"{}];",
+                    self.color(id),
+                    self.color(child),
+                    count,
+                    style
+                )?;
+            }
+        }
+        writeln!(out, "}}")
+    }
+}
+
+/// A problem with a rule set that would make queries on it meaningless.
+#[derive(Debug, PartialEq, Eq)]
+enum RuleError {
+    /// `color` appears inside `used_by` but has no rule of its own.
+    Undefined { color: String, used_by: String },
+    /// `color` has more than one rule.
+    Duplicate { color: String },
+    /// The rule for `used_by` holds more `color` bags than can be counted.
+    Count {
+        color: String,
+        used_by: String,
+        count: String,
+    },
+    /// A chain of bags that ends up inside itself; the first and last colours
+    /// are the same.
+    Cycle(Vec<String>),
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Undefined { color, used_by } => {
+                write!(f, "{} bags (inside {} bags) have no rule", color, used_by)
+            }
+            RuleError::Duplicate { color } => write!(f, "{} bags have more than one rule", color),
+            RuleError::Count {
+                color,
+                used_by,
+                count,
+            } => write!(
+                f,
+                "{} bags hold too many {} bags to count: {}",
+                used_by, color, count
+            ),
+            RuleError::Cycle(path) => write!(f, "bags contain themselves: {}", path.join(" -> ")),
+        }
+    }
+}
+
+/// Every problem found in a rule set.
+#[derive(Debug, PartialEq, Eq)]
+struct InvalidRules(Vec<RuleError>);
+
+impl fmt::Display for InvalidRules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.0.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
     }
-    sum
 }
 
-fn parse_rules(input: &str) -> HashMap<String, Rule> {
+impl std::error::Error for InvalidRules {}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    InProgress,
+    Done,
+}
+
+fn find_cycles<'a>(
+    ruleset: &'a RuleSet,
+    color: &'a str,
+    visits: &mut HashMap<&'a str, Visit>,
+    path: &mut Vec<&'a str>,
+    cycles: &mut Vec<RuleError>,
+) {
+    match visits.get(color) {
+        Some(Visit::Done) => return,
+        Some(Visit::InProgress) => {
+            let start = path.iter().position(|&c| c == color).unwrap_or(0);
+            let mut cycle: Vec<String> = path[start..].iter().map(|c| c.to_string()).collect();
+            cycle.push(color.to_string());
+            cycles.push(RuleError::Cycle(cycle));
+            return;
+        }
+        None => {}
+    }
+    let rule = match ruleset.get(color) {
+        Some(rule) => rule,
+        None => return,
+    };
+    visits.insert(color, Visit::InProgress);
+    path.push(color);
+    for content in &rule.contents {
+        find_cycles(ruleset, &content.color, visits, path, cycles);
+    }
+    path.pop();
+    visits.insert(color, Visit::Done);
+}
+
+fn validate_rules(ruleset: &RuleSet) -> Vec<RuleError> {
+    let mut colors: Vec<_> = ruleset.keys().collect();
+    colors.sort();
+
+    let mut errors = vec![];
+    for color in &colors {
+        for content in &ruleset[*color].contents {
+            if !ruleset.contains_key(&content.color) {
+                errors.push(RuleError::Undefined {
+                    color: content.color.clone(),
+                    used_by: color.to_string(),
+                });
+            }
+        }
+    }
+
+    let mut visits = HashMap::new();
+    for color in colors {
+        find_cycles(ruleset, color, &mut visits, &mut vec![], &mut errors);
+    }
+    errors
+}
+
+fn parse_rules(input: &str) -> Result<RuleSet, InvalidRules> {
     lazy_static! {
         static ref CONTENTS: Regex = Regex::new("(\\d+) (\\w+ \\w+) bags?,? ?").unwrap();
         static ref LINE: Regex = Regex::new("(?m)(\\w+ \\w+) bags contain (.*).$").unwrap();
     }
 
     let mut rules = HashMap::new();
+    let mut errors = vec![];
 
     for cap in LINE.captures_iter(input) {
-        rules.insert(
-            cap[1].to_string(),
-            Rule {
-                color: cap[1].to_string(),
-                contents: CONTENTS
-                    .captures_iter(&cap[2])
-                    .map(|c| Content {
-                        color: c[2].to_string(),
-                        count: c[1].parse().unwrap(),
-                    })
-                    .collect(),
-            },
-        );
+        let mut rule = Rule {
+            color: cap[1].to_string(),
+            contents: vec![],
+        };
+        for c in CONTENTS.captures_iter(&cap[2]) {
+            match c[1].parse() {
+                Ok(count) => rule.contents.push(Content {
+                    color: c[2].to_string(),
+                    count,
+                }),
+                Err(_) => errors.push(RuleError::Count {
+                    color: c[2].to_string(),
+                    used_by: rule.color.clone(),
+                    count: c[1].to_string(),
+                }),
+            }
+        }
+        if rules.contains_key(&rule.color) {
+            errors.push(RuleError::Duplicate { color: rule.color });
+            continue;
+        }
+        rules.insert(cap[1].to_string(), rule);
+    }
+
+    errors.extend(validate_rules(&rules));
+    if errors.is_empty() {
+        Ok(rules)
+    } else {
+        Err(InvalidRules(errors))
     }
-    rules
 }
 
-fn main() {
-    let input = std::fs::read_to_string("input.txt").unwrap();
-    let rules = parse_rules(&input);
+const COMMANDS: [&str; 5] = ["containers", "inside", "tree", "chain", "dot"];
 
-    // for each key, count those that have some path to "shiny gold" bags
-    let mut count = 0;
-    for rule in rules.keys() {
-        if let Some(_n) = traverse_to(&rules, rule, "shiny gold") {
-            count += 1;
-        }
+const USAGE: &str = "usage: aoc7 [RULES] [containers COLOR | inside COLOR | tree COLOR \
+                     | chain OUTER INNER | dot [--highlight COLOR] [--around COLOR] [--depth N]]";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1).peekable();
+    let mut input_path = "input.txt".to_string();
+    if let Some(path) = args.next_if(|arg| !COMMANDS.contains(&arg.as_str())) {
+        input_path = path;
     }
+    let input = std::fs::read_to_string(input_path)?;
+    let rules = parse_rules(&input)?;
+
+    let graph = BagGraph::new(&rules);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
 
-    let bag_total = count_below(&rules, &rules["shiny gold"], 1);
+    let command = args.next();
+    let mut color = || args.next().ok_or(USAGE);
+    match command.as_deref() {
+        None => {
+            // count those that have some path to "shiny gold" bags
+            let count = graph.containers("shiny gold").map_or(0, |c| c.len());
+            // no "shiny gold" bags means none inside them, but an overflow is an error
+            let bag_total = match graph.id("shiny gold") {
+                Some(_) => graph
+                    .bags_inside("shiny gold")
+                    .ok_or("too many bags inside shiny gold bags")?,
+                None => 0,
+            };
 
-    // for the "shiny gold" bag, count all bags necessary
-    println!(
-        "All Rules: {},\tTraversible: {},\tBag total {}",
-        rules.len(),
-        count,
-        bag_total
-    );
+            // for the "shiny gold" bag, count all bags necessary
+            println!(
+                "All Rules: {},\tTraversible: {},\tBag total {}",
+                rules.len(),
+                count,
+                bag_total
+            );
+        }
+        Some("containers") => {
+            let color = color()?;
+            let containers = graph
+                .containers(&color)
+                .ok_or_else(|| format!("no {} bags", color))?;
+            for container in &containers {
+                println!("{}", container);
+            }
+            println!("{} colours can hold {} bags", containers.len(), color);
+        }
+        Some("inside") => {
+            let color = color()?;
+            graph
+                .id(&color)
+                .ok_or_else(|| format!("no {} bags", color))?;
+            let total = graph
+                .bags_inside(&color)
+                .ok_or_else(|| format!("too many bags inside {} bags", color))?;
+            println!("{} bags hold {} bags", color, total);
+        }
+        Some("tree") => graph.write_tree(&mut out, &color()?)?,
+        Some("chain") => {
+            let (outer, inner) = (color()?, color()?);
+            match graph.shortest_chain(&outer, &inner) {
+                Some(shortest) => {
+                    let longest = graph.longest_chain(&outer, &inner).unwrap_or_default();
+                    println!("Shortest: {}", shortest.join(" -> "));
+                    println!("Longest: {}", longest.join(" -> "));
+                }
+                None => println!("{} bags can't hold {} bags", outer, inner),
+            }
+        }
+        Some("dot") => {
+            let mut options = DotOptions {
+                depth: 1,
+                ..DotOptions::default()
+            };
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--highlight" => options.highlight = args.next(),
+                    "--around" => options.around = args.next(),
+                    "--depth" => {
+                        options.depth = args.next().ok_or("--depth needs a number")?.parse()?
+                    }
+                    _ => return Err(format!("unknown option {:?}", arg).into()),
+                }
+            }
+            graph.write_dot(&mut out, &options)?;
+        }
+        Some(_) => return Err(USAGE.into()),
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -112,8 +644,11 @@ mod tests {
         let rules = parse_rules(
             "light red bags contain 1 bright white bag, 2 muted yellow bags.\n\
                                  bright white bags contain 1 shiny gold bag.\n\
+                                 muted yellow bags contain no other bags.\n\
+                                 shiny gold bags contain no other bags.\n\
                                  dotted black bags contain no other bags.",
-        );
+        )
+        .unwrap();
         let rule0 = &rules["light red"];
         let rule1 = &rules["bright white"];
         let rule2 = &rules["dotted black"];
@@ -139,8 +674,273 @@ mod tests {
                                  dark green bags contain 2 dark blue bags.\n\
                                  dark blue bags contain 2 dark violet bags.\n\
                                  dark violet bags contain no other bags.",
+        )
+        .unwrap();
+        let count = BagGraph::new(&rules).bags_inside("shiny gold");
+        assert_eq!(count, Some(126));
+    }
+
+    #[test]
+    fn test_graph() {
+        let rules = parse_rules(
+            "light red bags contain 1 bright white bag, 2 muted yellow bags.\n\
+             dark orange bags contain 3 bright white bags, 4 muted yellow bags.\n\
+             bright white bags contain 1 shiny gold bag.\n\
+             muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.\n\
+             shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.\n\
+             dark olive bags contain 3 faded blue bags, 4 dotted black bags.\n\
+             vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.\n\
+             faded blue bags contain no other bags.\n\
+             dotted black bags contain no other bags.",
+        )
+        .unwrap();
+        let graph = BagGraph::new(&rules);
+        assert_eq!(
+            graph.containers("shiny gold"),
+            Some(vec![
+                "bright white",
+                "dark orange",
+                "light red",
+                "muted yellow"
+            ])
+        );
+        assert_eq!(graph.containers("light red"), Some(vec![]));
+        assert_eq!(graph.containers("mauve"), None);
+        assert_eq!(graph.bags_inside("shiny gold"), Some(32));
+        assert_eq!(graph.bags_inside("faded blue"), Some(0));
+        assert_eq!(graph.bags_inside("shiny gold"), Some(32));
+    }
+
+    #[test]
+    fn test_deep_shared_graph() {
+        // Each level holds two of the next, so naive recursion would visit
+        // 2^depth paths.
+        let depth = 200;
+        let mut input = String::new();
+        for level in 0..depth {
+            input += &format!(
+                "level l{} bags contain 2 level l{} bags, 1 other l{} bag.\n",
+                level,
+                level + 1,
+                level + 1
+            );
+            input += &format!(
+                "other l{} bags contain 1 level l{} bag.\n",
+                level + 1,
+                level + 1
+            );
+        }
+        input += &format!("level l{} bags contain no other bags.\n", depth);
+        let rules = parse_rules(&input).unwrap();
+        let graph = BagGraph::new(&rules);
+        assert_eq!(
+            graph
+                .containers(&format!("level l{}", depth))
+                .unwrap()
+                .len(),
+            2 * depth
+        );
+        assert_eq!(graph.bags_inside("level l199"), Some(4));
+        assert_eq!(graph.bags_inside("level l198"), Some(4 + 3 * 4));
+    }
+
+    #[test]
+    fn test_bags_inside_overflow() {
+        // Each level holds three of the next, so the count passes usize::MAX
+        let depth = 80;
+        let mut input = String::new();
+        for level in 0..depth {
+            input += &format!(
+                "level l{} bags contain 3 level l{} bags.\n",
+                level,
+                level + 1
+            );
+        }
+        input += &format!("level l{} bags contain no other bags.\n", depth);
+        let graph = BagGraph::new(&parse_rules(&input).unwrap());
+        assert_eq!(graph.bags_inside("level l0"), None);
+        assert_eq!(graph.bags_inside("level l0"), None);
+        assert_eq!(graph.bags_inside("level l78"), Some(12));
+        assert_eq!(
+            graph.bags_inside("level l41"),
+            Some((3usize.pow(40) - 3) / 2)
+        );
+        assert!(graph.write_tree(&mut vec![], "level l0").is_err());
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let errors = parse_rules(
+            "light red bags contain 1 bright white bag, 2 muted yellow bags.\n\
+             bright white bags contain 1 shiny gold bag.\n\
+             shiny gold bags contain 3 light red bags.\n\
+             dotted black bags contain 1 dotted black bag.\n\
+             dotted black bags contain no other bags.",
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors.0,
+            vec![
+                RuleError::Duplicate {
+                    color: "dotted black".to_string()
+                },
+                RuleError::Undefined {
+                    color: "muted yellow".to_string(),
+                    used_by: "light red".to_string()
+                },
+                RuleError::Cycle(vec![
+                    "bright white".to_string(),
+                    "shiny gold".to_string(),
+                    "light red".to_string(),
+                    "bright white".to_string()
+                ]),
+                RuleError::Cycle(vec!["dotted black".to_string(), "dotted black".to_string()]),
+            ]
+        );
+        assert_eq!(
+            errors.to_string().lines().nth(2),
+            Some(
+                "bags contain themselves: bright white -> shiny gold -> light red -> bright white"
+            )
+        );
+
+        let errors = parse_rules(
+            "light red bags contain 99999999999999999999999 bright white bags.\n\
+             bright white bags contain no other bags.",
+        )
+        .unwrap_err();
+        assert_eq!(
+            errors.to_string(),
+            "light red bags hold too many bright white bags to count: 99999999999999999999999"
+        );
+    }
+
+    #[test]
+    fn test_dot() {
+        let rules = parse_rules(
+            "light red bags contain 1 bright white bag, 2 muted yellow bags.\n\
+             bright white bags contain 1 shiny gold bag.\n\
+             muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.\n\
+             shiny gold bags contain 1 dark olive bag.\n\
+             dark olive bags contain 3 faded blue bags.\n\
+             faded blue bags contain no other bags.",
+        )
+        .unwrap();
+        let graph = BagGraph::new(&rules);
+        let dot = |options| {
+            let mut out = vec![];
+            graph.write_dot(&mut out, &options).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let all = dot(DotOptions::default());
+        assert!(all.starts_with("digraph bags {\n    \"bright white\";\n"));
+        assert!(all.contains("    \"muted yellow\" -> \"faded blue\" [label=\"9\"];\n"));
+        assert_eq!(all.matches("->").count(), 7);
+
+        let highlighted = dot(DotOptions {
+            highlight: Some("shiny gold".to_string()),
+            ..DotOptions::default()
+        });
+        assert!(highlighted.contains("    \"shiny gold\" [style=filled, fillcolor=gold];\n"));
+        assert!(highlighted.contains("    \"light red\" [style=filled, fillcolor=lightblue];\n"));
+        assert!(highlighted.contains("    \"faded blue\" [style=filled, fillcolor=palegreen];\n"));
+        assert!(highlighted
+            .contains("    \"bright white\" -> \"shiny gold\" [label=\"1\", color=blue];\n"));
+        assert!(highlighted
+            .contains("    \"dark olive\" -> \"faded blue\" [label=\"3\", color=darkgreen];\n"));
+        assert!(highlighted.contains("    \"muted yellow\" -> \"faded blue\" [label=\"9\"];\n"));
+
+        let around = dot(DotOptions {
+            around: Some("dark olive".to_string()),
+            depth: 1,
+            ..DotOptions::default()
+        });
+        assert_eq!(
+            around,
+            "digraph bags {\n\
+             \x20   \"dark olive\";\n\
+             \x20   \"faded blue\";\n\
+             \x20   \"shiny gold\";\n\
+             \x20   \"dark olive\" -> \"faded blue\" [label=\"3\"];\n\
+             \x20   \"shiny gold\" -> \"dark olive\" [label=\"1\"];\n\
+             }\n"
+        );
+
+        let mut out = vec![];
+        let missing = DotOptions {
+            around: Some("mauve".to_string()),
+            ..DotOptions::default()
+        };
+        assert!(graph.write_dot(&mut out, &missing).is_err());
+    }
+
+    #[test]
+    fn test_chains() {
+        let rules = parse_rules(
+            "light red bags contain 1 bright white bag, 2 muted yellow bags.\n\
+             bright white bags contain 1 shiny gold bag.\n\
+             muted yellow bags contain 2 bright white bags, 9 faded blue bags.\n\
+             shiny gold bags contain 1 dark olive bag.\n\
+             dark olive bags contain 3 faded blue bags.\n\
+             faded blue bags contain no other bags.",
+        )
+        .unwrap();
+        let graph = BagGraph::new(&rules);
+        assert_eq!(
+            graph.shortest_chain("light red", "shiny gold"),
+            Some(vec!["light red", "bright white", "shiny gold"])
+        );
+        assert_eq!(
+            graph.longest_chain("light red", "shiny gold"),
+            Some(vec![
+                "light red",
+                "muted yellow",
+                "bright white",
+                "shiny gold"
+            ])
+        );
+        assert_eq!(
+            graph.shortest_chain("light red", "faded blue"),
+            Some(vec!["light red", "muted yellow", "faded blue"])
+        );
+        assert_eq!(
+            graph.longest_chain("light red", "faded blue"),
+            Some(vec![
+                "light red",
+                "muted yellow",
+                "bright white",
+                "shiny gold",
+                "dark olive",
+                "faded blue"
+            ])
+        );
+        assert_eq!(graph.shortest_chain("shiny gold", "light red"), None);
+        assert_eq!(graph.longest_chain("shiny gold", "light red"), None);
+        assert_eq!(graph.shortest_chain("faded blue", "faded blue"), None);
+        assert_eq!(graph.longest_chain("mauve", "faded blue"), None);
+    }
+
+    #[test]
+    fn test_tree() {
+        let rules = parse_rules(
+            "shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.\n\
+             dark olive bags contain 3 faded blue bags.\n\
+             vibrant plum bags contain 5 faded blue bags.\n\
+             faded blue bags contain no other bags.",
+        )
+        .unwrap();
+        let graph = BagGraph::new(&rules);
+        let mut out = vec![];
+        graph.write_tree(&mut out, "shiny gold").unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "shiny gold\n\
+             \x20 1 dark olive (1 total)\n\
+             \x20   3 faded blue (3 total)\n\
+             \x20 2 vibrant plum (2 total)\n\
+             \x20   5 faded blue (10 total)\n"
         );
-        let count = count_below(&rules, &rules["shiny gold"], 1);
-        assert_eq!(count, 126);
+        assert!(graph.write_tree(&mut vec![], "mauve").is_err());
     }
 }
@@ -1,32 +1,307 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
 
+/// One of the machine's registers: the accumulator, or `a` through `z`.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum Instruction {
+pub struct Register(u8);
+
+impl Register {
+    pub const ACC: Register = Register(0);
+    const COUNT: usize = 27;
+
+    fn parse(name: &str) -> Result<Self, String> {
+        match name.as_bytes() {
+            b"acc" => Ok(Register::ACC),
+            &[c @ b'a'..=b'z'] => Ok(Register(c - b'a' + 1)),
+            _ => Err(format!("unknown register {:?}", name)),
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            0 => write!(f, "acc"),
+            n => write!(f, "{}", (b'a' + n - 1) as char),
+        }
+    }
+}
+
+/// An instruction argument: a register, or a literal value.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Operand {
+    Register(Register),
+    Value(isize),
+}
+
+impl Operand {
+    fn parse(arg: &str) -> Result<Self, String> {
+        match arg.parse() {
+            Ok(value) => Ok(Operand::Value(value)),
+            Err(_) => Register::parse(arg).map(Operand::Register),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Register(register) => write!(f, "{}", register),
+            Operand::Value(value) => write!(f, "{:+}", value),
+        }
+    }
+}
+
+/// Everything an instruction can change besides the instruction pointer.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Cpu {
+    registers: [isize; Register::COUNT],
+    pub output: Vec<isize>,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Cpu {
+            registers: [0; Register::COUNT],
+            output: vec![],
+        }
+    }
+}
+
+impl Cpu {
+    pub fn get(&self, register: Register) -> isize {
+        self.registers[register.0 as usize]
+    }
+
+    pub fn set(&mut self, register: Register, value: isize) {
+        self.registers[register.0 as usize] = value;
+    }
+
+    pub fn value(&self, operand: Operand) -> isize {
+        match operand {
+            Operand::Register(register) => self.get(register),
+            Operand::Value(value) => value,
+        }
+    }
+
+    pub fn acc(&self) -> isize {
+        self.get(Register::ACC)
+    }
+}
+
+/// Where execution goes after an instruction.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Flow {
+    Next,
+    Jump(isize),
+    Halt,
+}
+
+/// An instruction outside the built-in set. Register a parser for it with
+/// `Parser::with_instruction`.
+pub trait Custom: fmt::Debug {
+    fn name(&self) -> &str;
+    /// The arguments as they'd be written in source.
+    fn args(&self) -> Vec<String>;
+    fn execute(&self, cpu: &mut Cpu) -> Result<Flow, String>;
+}
+
+pub type CustomParser = fn(&[&str]) -> Result<Rc<dyn Custom>, String>;
+
+#[derive(Debug, Clone)]
+pub enum Instruction {
     Nop(isize),
     Acc(isize),
     Jmp(isize),
+    Set(Register, Operand),
+    Add(Register, Operand),
+    Sub(Register, Operand),
+    Mul(Register, Operand),
+    Div(Register, Operand),
+    /// Jump by the offset if the value is zero.
+    Jz(Operand, isize),
+    /// Jump by the offset if the value is not zero.
+    Jnz(Operand, isize),
+    Out(Operand),
+    Hlt,
+    Custom(Rc<dyn Custom>),
+}
+
+impl PartialEq for Instruction {
+    fn eq(&self, other: &Self) -> bool {
+        use Instruction::*;
+        match (self, other) {
+            (Nop(a), Nop(b)) | (Acc(a), Acc(b)) | (Jmp(a), Jmp(b)) => a == b,
+            (Set(r, a), Set(s, b))
+            | (Add(r, a), Add(s, b))
+            | (Sub(r, a), Sub(s, b))
+            | (Mul(r, a), Mul(s, b))
+            | (Div(r, a), Div(s, b)) => r == s && a == b,
+            (Jz(a, x), Jz(b, y)) | (Jnz(a, x), Jnz(b, y)) => a == b && x == y,
+            (Out(a), Out(b)) => a == b,
+            (Hlt, Hlt) => true,
+            // Custom instructions are only equal to themselves
+            (Custom(a), Custom(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
 }
 
-fn parse_instruction(line: &str) -> Instruction {
-    match &line[0..3] {
-        "nop" => Instruction::Nop(line[4..].parse().unwrap()),
-        "jmp" => Instruction::Jmp(line[4..].parse().unwrap()),
-        "acc" => Instruction::Acc(line[4..].parse().unwrap()),
-        _ => unreachable!(),
+impl Eq for Instruction {}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+        match self {
+            Nop(val) => write!(f, "nop {:+}", val),
+            Acc(val) => write!(f, "acc {:+}", val),
+            Jmp(val) => write!(f, "jmp {:+}", val),
+            Set(reg, op) => write!(f, "set {} {}", reg, op),
+            Add(reg, op) => write!(f, "add {} {}", reg, op),
+            Sub(reg, op) => write!(f, "sub {} {}", reg, op),
+            Mul(reg, op) => write!(f, "mul {} {}", reg, op),
+            Div(reg, op) => write!(f, "div {} {}", reg, op),
+            Jz(op, val) => write!(f, "jz {} {:+}", op, val),
+            Jnz(op, val) => write!(f, "jnz {} {:+}", op, val),
+            Out(op) => write!(f, "out {}", op),
+            Hlt => write!(f, "hlt"),
+            Custom(custom) => {
+                write!(f, "{}", custom.name())?;
+                for arg in custom.args() {
+                    write!(f, " {}", arg)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A line of source that couldn't be compiled. Lines start at 1.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Turns source into instructions, including any custom ones.
+#[derive(Default)]
+pub struct Parser {
+    custom: HashMap<String, CustomParser>,
+}
+
+fn offset(arg: &str) -> Result<isize, String> {
+    arg.parse().map_err(|_| format!("invalid offset {:?}", arg))
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Parser::default()
+    }
+
+    pub fn with_instruction(mut self, name: &str, parse: CustomParser) -> Self {
+        self.custom.insert(name.to_string(), parse);
+        self
+    }
+
+    pub fn parse_instruction(&self, line: &str) -> Result<Instruction, String> {
+        use Instruction::*;
+        let mut words = line.split_whitespace();
+        let opcode = words.next().ok_or("missing opcode")?;
+        let args: Vec<&str> = words.collect();
+        let instruction = match (opcode, args.as_slice()) {
+            ("nop", [val]) => Nop(offset(val)?),
+            ("acc", [val]) => Acc(offset(val)?),
+            ("jmp", [val]) => Jmp(offset(val)?),
+            ("set", [reg, op]) => Set(Register::parse(reg)?, Operand::parse(op)?),
+            ("add", [reg, op]) => Add(Register::parse(reg)?, Operand::parse(op)?),
+            ("sub", [reg, op]) => Sub(Register::parse(reg)?, Operand::parse(op)?),
+            ("mul", [reg, op]) => Mul(Register::parse(reg)?, Operand::parse(op)?),
+            ("div", [reg, op]) => Div(Register::parse(reg)?, Operand::parse(op)?),
+            ("jz", [op, val]) => Jz(Operand::parse(op)?, offset(val)?),
+            ("jnz", [op, val]) => Jnz(Operand::parse(op)?, offset(val)?),
+            ("out", [op]) => Out(Operand::parse(op)?),
+            ("hlt", []) => Hlt,
+            (
+                "nop" | "acc" | "jmp" | "set" | "add" | "sub" | "mul" | "div" | "jz" | "jnz"
+                | "out" | "hlt",
+                _,
+            ) => {
+                return Err(format!(
+                    "wrong number of arguments for {:?}: {}",
+                    opcode,
+                    args.len()
+                ))
+            }
+            _ => match self.custom.get(opcode) {
+                Some(parse) => Custom(parse(&args)?),
+                None => return Err(format!("unknown opcode {:?}", opcode)),
+            },
+        };
+        Ok(instruction)
+    }
+
+    pub fn compile(&self, source: &str) -> Result<Vec<Instruction>, ParseError> {
+        source
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                self.parse_instruction(line).map_err(|message| ParseError {
+                    line: i + 1,
+                    message,
+                })
+            })
+            .collect()
     }
 }
 
+/// How a program decides it is stuck in an infinite loop.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum LoopCheck {
+    /// Running any instruction a second time, as boot code does.
+    #[default]
+    Revisit,
+    /// Reaching an instruction with every register as it was the last time,
+    /// for programs that loop on purpose.
+    State,
+}
+
+/// How a run of a program ended.
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Terminated,
+    Looped,
+    /// An instruction failed, with the error it gave.
+    Failed(String),
+}
+
 #[derive(Debug, Default)]
 struct Program<'a> {
     instructions: &'a [Instruction],
-    state: isize,
+    cpu: Cpu,
     pointer: usize,
     visited: BTreeSet<usize>,
+    halted: bool,
+    loop_check: LoopCheck,
+    states: HashSet<(usize, [isize; Register::COUNT])>,
+    /// `(pointer, accumulator after)` for every instruction run, in order,
+    /// if `record_history` is on.
+    history: Vec<(usize, isize)>,
+    record_history: bool,
 }
 
 impl<'a> Program<'a> {
-    fn compile(source: &str) -> Vec<Instruction> {
-        source.lines().map(parse_instruction).collect()
+    fn compile(source: &str) -> Result<Vec<Instruction>, ParseError> {
+        Parser::new().compile(source)
     }
 
     fn with_instructions(instructions: &'a [Instruction]) -> Self {
@@ -36,75 +311,974 @@ impl<'a> Program<'a> {
         }
     }
 
-    fn can_terminate(&mut self) -> bool {
-        let max = self.instructions.len();
+    fn with_loop_check(mut self, loop_check: LoopCheck) -> Self {
+        self.loop_check = loop_check;
+        self
+    }
+
+    fn with_history(mut self) -> Self {
+        self.record_history = true;
+        self
+    }
 
-        loop {
-            if self.pointer >= max {
-                return true;
-            }
+    fn is_finished(&self) -> bool {
+        self.halted || self.pointer >= self.instructions.len()
+    }
 
-            match self.step() {
-                Ok(_) => continue,
-                Err(_) => break,
+    /// Whether the next step would repeat itself under the loop check.
+    fn is_looping(&self) -> bool {
+        match self.loop_check {
+            LoopCheck::Revisit => self.visited.contains(&self.pointer),
+            LoopCheck::State => self.states.contains(&(self.pointer, self.cpu.registers)),
+        }
+    }
+
+    fn run(&mut self) -> Outcome {
+        while !self.is_finished() {
+            if self.is_looping() {
+                return Outcome::Looped;
+            }
+            if let Err(e) = self.step() {
+                return Outcome::Failed(e);
             }
         }
+        Outcome::Terminated
+    }
 
-        false
+    fn execute(&mut self) -> Result<Flow, String> {
+        use Instruction::*;
+        let pointer = self.pointer;
+        let overflow = || format!("Overflow at {}", pointer);
+        let cpu = &mut self.cpu;
+        let flow = match &self.instructions[pointer] {
+            Nop(_) => Flow::Next,
+            Acc(val) => {
+                let value = cpu.acc().checked_add(*val).ok_or_else(overflow)?;
+                cpu.set(Register::ACC, value);
+                Flow::Next
+            }
+            Jmp(val) => Flow::Jump(*val),
+            Set(reg, op) => {
+                cpu.set(*reg, cpu.value(*op));
+                Flow::Next
+            }
+            Add(reg, op) => {
+                let value = cpu.get(*reg).checked_add(cpu.value(*op));
+                cpu.set(*reg, value.ok_or_else(overflow)?);
+                Flow::Next
+            }
+            Sub(reg, op) => {
+                let value = cpu.get(*reg).checked_sub(cpu.value(*op));
+                cpu.set(*reg, value.ok_or_else(overflow)?);
+                Flow::Next
+            }
+            Mul(reg, op) => {
+                let value = cpu.get(*reg).checked_mul(cpu.value(*op));
+                cpu.set(*reg, value.ok_or_else(overflow)?);
+                Flow::Next
+            }
+            Div(reg, op) => {
+                let divisor = cpu.value(*op);
+                if divisor == 0 {
+                    return Err(format!("Division by zero at {}", pointer));
+                }
+                let value = cpu.get(*reg).checked_div(divisor);
+                cpu.set(*reg, value.ok_or_else(overflow)?);
+                Flow::Next
+            }
+            Jz(op, val) if cpu.value(*op) == 0 => Flow::Jump(*val),
+            Jnz(op, val) if cpu.value(*op) != 0 => Flow::Jump(*val),
+            Jz(..) | Jnz(..) => Flow::Next,
+            Out(op) => {
+                let value = cpu.value(*op);
+                cpu.output.push(value);
+                Flow::Next
+            }
+            Hlt => Flow::Halt,
+            Custom(custom) => custom.execute(cpu)?,
+        };
+        Ok(flow)
     }
 
     fn step(&mut self) -> Result<(), String> {
-        use Instruction::*;
-        if self.visited.contains(&self.pointer) {
+        if self.is_finished() {
+            return Err("Program has terminated".to_string());
+        }
+        if self.is_looping() {
             return Err(format!("Already run {}", self.pointer));
         }
+        if self.loop_check == LoopCheck::State {
+            self.states.insert((self.pointer, self.cpu.registers));
+        }
         self.visited.insert(self.pointer);
-        match &self.instructions[self.pointer] {
-            Nop(_) => self.pointer += 1,
-            Acc(val) => {
-                self.state = self.state + val;
-                self.pointer += 1
+        let flow = self.execute()?;
+        if self.record_history {
+            self.history.push((self.pointer, self.cpu.acc()));
+        }
+        match flow {
+            Flow::Next => self.pointer += 1,
+            Flow::Jump(val) => {
+                self.pointer = match (self.pointer as isize).checked_add(val) {
+                    Some(target) if target >= 0 => target as usize,
+                    // Jumping before the start ends the program, as past the end does
+                    Some(_) => self.instructions.len(),
+                    None => return Err(format!("Overflow at {}", self.pointer)),
+                }
             }
-            Jmp(val) => self.pointer = (self.pointer as isize + val) as usize,
+            Flow::Halt => self.halted = true,
         }
         Ok(())
     }
 }
 
-fn main() {
+/// The single `jmp`/`nop` flip that lets a looping program terminate.
+#[derive(Debug, PartialEq, Eq)]
+struct Repair {
+    index: usize,
+    flipped: Instruction,
+    /// The accumulator once the repaired program terminates.
+    acc: isize,
+    /// Whether no other single flip would also work.
+    unique: bool,
+}
+
+/// The instruction `offset` away from `i`, or `end` if that's outside the
+/// program.
+fn jump_target(end: usize, i: usize, offset: isize) -> usize {
+    match (i as isize).checked_add(offset) {
+        Some(target) if target >= 0 && (target as usize) < end => target as usize,
+        _ => end,
+    }
+}
+
+/// Where control goes after instruction `i`, or `None` if that depends on
+/// more than the instruction itself.
+fn successor(instructions: &[Instruction], i: usize) -> Result<Option<usize>, String> {
+    use Instruction::*;
+    let end = instructions.len();
+    let target = |offset| jump_target(end, i, offset);
+    match &instructions[i] {
+        Nop(_) | Acc(_) | Set(..) | Add(..) | Sub(..) | Mul(..) | Div(..) | Out(_) => {
+            Ok(Some(target(1)))
+        }
+        Jmp(val) => Ok(Some(target(*val))),
+        Hlt => Ok(None),
+        instruction => Err(format!(
+            "can't repair around {:?} at {}: its flow depends on the data",
+            instruction.to_string(),
+            i
+        )),
+    }
+}
+
+/// Finds the flip that fixes a looping program in time linear in its length.
+///
+/// Control flow is fixed for boot code, so the instructions that eventually
+/// terminate can be found by walking the graph backwards from the end once.
+/// A flip fixes the program if it's on the path the program actually takes
+/// and sends control to one of those instructions. Returns `None` if the
+/// program already terminates or no single flip works.
+fn repair(instructions: &[Instruction]) -> Result<Option<Repair>, String> {
     use Instruction::*;
-    let input = std::fs::read_to_string("input.txt").unwrap();
-    let mut instructions = Program::compile(&input);
-    let mut program = Program::with_instructions(instructions.as_slice());
-    if !program.can_terminate() {
-        println!("Infinite loop detected. Program state: {}", program.state);
-    }
-    for i in 0..instructions.len() {
-        let instruction = instructions[i];
-        let flipped = match instruction {
-            Acc(_) => continue,
-            Jmp(val) => Nop(val),
-            Nop(val) => Jmp(val),
+    let end = instructions.len();
+
+    // terminates[i] for every instruction, and end itself, that reaches the end
+    let mut predecessors = vec![vec![]; end + 1];
+    let mut terminates = vec![false; end + 1];
+    let mut stack = vec![end];
+    terminates[end] = true;
+    for (i, terminal) in terminates.iter_mut().enumerate().take(end) {
+        match successor(instructions, i)? {
+            Some(next) => predecessors[next].push(i),
+            None => {
+                *terminal = true;
+                stack.push(i);
+            }
+        }
+    }
+    while let Some(node) = stack.pop() {
+        for &previous in &predecessors[node] {
+            if !terminates[previous] {
+                terminates[previous] = true;
+                stack.push(previous);
+            }
+        }
+    }
+    if terminates[0] || end == 0 {
+        return Ok(None);
+    }
+
+    // Follow the program until it loops, noting flips that escape
+    let mut on_path = vec![false; end];
+    let mut candidates = vec![];
+    let mut pointer = 0;
+    while pointer < end && !on_path[pointer] {
+        on_path[pointer] = true;
+        let flipped = match instructions[pointer] {
+            Jmp(val) => Some(Nop(val)),
+            Nop(val) => Some(Jmp(val)),
+            _ => None,
         };
-        instructions[i] = flipped;
-        let mut program = Program::with_instructions(instructions.as_slice());
-        if program.can_terminate() {
-            println!("Final state after fix: {}", program.state);
-            break;
-        } else {
-            instructions[i] = instruction;
+        if let Some(flipped) = flipped {
+            let offset = match flipped {
+                Jmp(val) => val,
+                _ => 1,
+            };
+            if terminates[jump_target(end, pointer, offset)] {
+                candidates.push((pointer, flipped));
+            }
         }
+        pointer = successor(instructions, pointer)?.unwrap_or(end);
+    }
+
+    let unique = candidates.len() == 1;
+    let (index, flipped) = match candidates.into_iter().next() {
+        Some(candidate) => candidate,
+        None => return Ok(None),
+    };
+    let mut patched = instructions.to_vec();
+    patched[index] = flipped.clone();
+    let mut program = Program::with_instructions(&patched);
+    match program.run() {
+        Outcome::Terminated => {}
+        Outcome::Looped => return Err(format!("flipping {} didn't terminate", index)),
+        Outcome::Failed(e) => return Err(format!("flipping {} failed: {}", index, e)),
+    }
+    Ok(Some(Repair {
+        index,
+        flipped,
+        acc: program.cpu.acc(),
+        unique,
+    }))
+}
+
+/// Writes instructions back out as source that `Program::compile` accepts.
+fn assemble(instructions: &[Instruction]) -> String {
+    instructions
+        .iter()
+        .map(|instruction| format!("{}\n", instruction))
+        .collect()
+}
+
+/// The absolute target of a jump at `i`, if the instruction can jump.
+fn jump_destination(instruction: &Instruction, i: usize) -> Option<isize> {
+    use Instruction::*;
+    match instruction {
+        // Saturating still lands outside the program, which is all that matters
+        Jmp(val) | Jz(_, val) | Jnz(_, val) => Some((i as isize).saturating_add(*val)),
+        _ => None,
     }
 }
 
+/// Writes a listing of the program split into basic blocks. Each block
+/// starts with a label noting the jumps that lead to it, and each jump notes
+/// the label it goes to.
+fn disassemble<W: Write>(instructions: &[Instruction], out: &mut W) -> io::Result<()> {
+    use Instruction::*;
+    let end = instructions.len();
+    let mut leaders = BTreeSet::new();
+    let mut sources: HashMap<usize, Vec<usize>> = HashMap::new();
+    leaders.insert(0);
+    for (i, instruction) in instructions.iter().enumerate() {
+        if let Some(target) = jump_destination(instruction, i) {
+            if target >= 0 && (target as usize) < end {
+                leaders.insert(target as usize);
+                sources.entry(target as usize).or_default().push(i);
+            }
+        }
+        if matches!(instruction, Jmp(_) | Jz(..) | Jnz(..) | Hlt) {
+            leaders.insert(i + 1);
+        }
+    }
+
+    for (i, instruction) in instructions.iter().enumerate() {
+        if leaders.contains(&i) {
+            if i != 0 {
+                writeln!(out)?;
+            }
+            match sources.get(&i) {
+                Some(from) => {
+                    let from: Vec<String> = from.iter().map(|f| f.to_string()).collect();
+                    writeln!(out, "L{}:{:16}; from {}", i, "", from.join(", "))?;
+                }
+                None => writeln!(out, "L{}:", i)?,
+            }
+        }
+        let text = instruction.to_string();
+        match jump_destination(instruction, i) {
+            Some(target) if target >= 0 && (target as usize) < end => {
+                writeln!(out, "{:>6}  {:<16}; -> L{}", i, text, target)?
+            }
+            Some(_) => writeln!(out, "{:>6}  {:<16}; -> end", i, text)?,
+            None => writeln!(out, "{:>6}  {}", i, text)?,
+        }
+    }
+    Ok(())
+}
+
+/// Runs the program, writing each instruction as it runs along with the
+/// accumulator afterwards. Returns whether the program terminated.
+fn trace<W: Write>(program: &mut Program, out: &mut W) -> io::Result<bool> {
+    while !program.is_finished() {
+        let pointer = program.pointer;
+        if let Err(e) = program.step() {
+            writeln!(out, "{}", e)?;
+            return Ok(false);
+        }
+        writeln!(
+            out,
+            "{:>4}: {:<12} acc={}",
+            pointer,
+            program.instructions[pointer].to_string(),
+            program.cpu.acc()
+        )?;
+    }
+    writeln!(out, "terminated, acc={}", program.cpu.acc())?;
+    Ok(true)
+}
+
+const DEBUGGER_HELP: &str = "\
+break N      stop before running instruction N
+delete N     remove the breakpoint at N
+step [N]     run N instructions (default 1)
+continue     run until a breakpoint, a loop or the end
+loop         run until a loop or the end, ignoring breakpoints
+print        show the pointer, the next instruction and the registers
+visited      list every instruction run so far
+history [N]  show the last N instructions run (default 10)
+reset        start the program again
+quit         leave the debugger";
+
+/// Runs a program a command at a time, reading commands from one stream and
+/// writing results to another.
+struct Debugger<'a> {
+    program: Program<'a>,
+    breakpoints: BTreeSet<usize>,
+}
+
+impl<'a> Debugger<'a> {
+    fn new(program: Program<'a>) -> Self {
+        Debugger {
+            program: program.with_history(),
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    fn status<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let program = &self.program;
+        match program.instructions.get(program.pointer) {
+            Some(instruction) if !program.halted => writeln!(
+                out,
+                "{:>4}: {:<12} acc={}",
+                program.pointer,
+                instruction.to_string(),
+                program.cpu.acc()
+            ),
+            _ => writeln!(out, "terminated, acc={}", program.cpu.acc()),
+        }
+    }
+
+    /// Steps until `limit` steps have run, a breakpoint is reached (if
+    /// `use_breakpoints`), or the program can't go on. A limit of 0 runs
+    /// nothing.
+    fn run_until<W: Write>(
+        &mut self,
+        out: &mut W,
+        limit: Option<usize>,
+        use_breakpoints: bool,
+    ) -> io::Result<()> {
+        if limit == Some(0) {
+            return self.status(out);
+        }
+        let mut steps = 0;
+        loop {
+            if let Err(e) = self.program.step() {
+                writeln!(out, "{}", e)?;
+                break;
+            }
+            steps += 1;
+            if self.program.is_finished() || limit == Some(steps) {
+                break;
+            }
+            if use_breakpoints && self.breakpoints.contains(&self.program.pointer) {
+                writeln!(out, "breakpoint at {}", self.program.pointer)?;
+                break;
+            }
+        }
+        self.status(out)
+    }
+
+    /// Runs one command, returning false when the debugger should exit.
+    fn command<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(true),
+        };
+        let arg = words.next().map(|w| w.parse::<usize>());
+        match (command, arg) {
+            ("b" | "break", Some(Ok(n))) => {
+                self.breakpoints.insert(n);
+                writeln!(out, "breakpoint set at {}", n)?;
+            }
+            ("d" | "delete", Some(Ok(n))) => {
+                if self.breakpoints.remove(&n) {
+                    writeln!(out, "breakpoint removed at {}", n)?;
+                } else {
+                    writeln!(out, "no breakpoint at {}", n)?;
+                }
+            }
+            ("s" | "step", None) => self.run_until(out, Some(1), false)?,
+            ("s" | "step", Some(Ok(n))) => self.run_until(out, Some(n), false)?,
+            ("c" | "continue", None) => self.run_until(out, None, true)?,
+            ("l" | "loop", None) => self.run_until(out, None, false)?,
+            ("p" | "print", None) => {
+                self.status(out)?;
+                let cpu = &self.program.cpu;
+                let registers: Vec<String> = (1..Register::COUNT as u8)
+                    .map(Register)
+                    .filter(|&r| cpu.get(r) != 0)
+                    .map(|r| format!("{}={}", r, cpu.get(r)))
+                    .collect();
+                if !registers.is_empty() {
+                    writeln!(out, "registers: {}", registers.join(" "))?;
+                }
+                if !cpu.output.is_empty() {
+                    writeln!(out, "output: {:?}", cpu.output)?;
+                }
+            }
+            ("v" | "visited", None) => {
+                let visited: Vec<String> =
+                    self.program.visited.iter().map(|v| v.to_string()).collect();
+                writeln!(out, "visited: {}", visited.join(" "))?;
+            }
+            ("h" | "history", arg @ (None | Some(Ok(_)))) => {
+                let count = arg.map_or(10, |n| n.unwrap_or(10));
+                let history = &self.program.history;
+                for &(pointer, acc) in &history[history.len().saturating_sub(count)..] {
+                    writeln!(
+                        out,
+                        "{:>4}: {:<12} acc={}",
+                        pointer,
+                        self.program.instructions[pointer].to_string(),
+                        acc
+                    )?;
+                }
+            }
+            ("r" | "reset", None) => {
+                self.program = Program::with_instructions(self.program.instructions)
+                    .with_loop_check(self.program.loop_check)
+                    .with_history();
+                self.status(out)?;
+            }
+            ("q" | "quit", None) => return Ok(false),
+            _ => writeln!(out, "{}", DEBUGGER_HELP)?,
+        }
+        Ok(true)
+    }
+
+    fn run<R: BufRead, W: Write>(&mut self, input: R, out: &mut W) -> io::Result<()> {
+        self.status(out)?;
+        write!(out, "(dbg) ")?;
+        out.flush()?;
+        for line in input.lines() {
+            if !self.command(&line?, out)? {
+                return Ok(());
+            }
+            write!(out, "(dbg) ")?;
+            out.flush()?;
+        }
+        writeln!(out)
+    }
+}
+
+/// What to do with the program.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Mode {
+    Run,
+    Debug,
+    Trace,
+    Disassemble,
+    Assemble,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut input_path = "input.txt".to_string();
+    let mut loop_check = LoopCheck::Revisit;
+    let mut mode = Mode::Run;
+    for arg in std::env::args().skip(1) {
+        let chosen = match arg.as_str() {
+            "--state-loops" => {
+                loop_check = LoopCheck::State;
+                continue;
+            }
+            "--debug" => Mode::Debug,
+            "--trace" => Mode::Trace,
+            "--disassemble" => Mode::Disassemble,
+            "--assemble" => Mode::Assemble,
+            _ => {
+                input_path = arg;
+                continue;
+            }
+        };
+        if mode != Mode::Run && mode != chosen {
+            return Err(format!("{} can't be combined with {:?} mode", arg, mode).into());
+        }
+        mode = chosen;
+    }
+
+    let input = std::fs::read_to_string(input_path)?;
+    let instructions = Program::compile(&input)?;
+    let mut program =
+        Program::with_instructions(instructions.as_slice()).with_loop_check(loop_check);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match mode {
+        Mode::Debug => {
+            let stdin = io::stdin();
+            return Ok(Debugger::new(program).run(stdin.lock(), &mut out)?);
+        }
+        Mode::Trace => {
+            trace(&mut program, &mut out)?;
+            return Ok(());
+        }
+        Mode::Disassemble => return Ok(disassemble(&instructions, &mut out)?),
+        Mode::Assemble => return Ok(write!(out, "{}", assemble(&instructions))?),
+        Mode::Run => {}
+    }
+    drop(out);
+    let outcome = program.run();
+    if !program.cpu.output.is_empty() {
+        println!("Output: {:?}", program.cpu.output);
+    }
+    match outcome {
+        Outcome::Terminated => {
+            println!("Program terminated. Program state: {}", program.cpu.acc());
+            return Ok(());
+        }
+        Outcome::Failed(e) => return Err(e.into()),
+        Outcome::Looped => {}
+    }
+    println!(
+        "Infinite loop detected. Program state: {}",
+        program.cpu.acc()
+    );
+    match repair(&instructions) {
+        Ok(Some(fix)) => {
+            println!(
+                "Flipping {} to {:?} fixes it{}",
+                fix.index,
+                fix.flipped.to_string(),
+                if fix.unique {
+                    ""
+                } else {
+                    " (other flips also work)"
+                }
+            );
+            println!("Final state after fix: {}", fix.acc);
+        }
+        Ok(None) => println!("No single flip fixes the program"),
+        Err(e) => println!("Can't look for a repair: {}", e),
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn parse_instruction(line: &str) -> Result<Instruction, String> {
+        Parser::new().parse_instruction(line)
+    }
+
     #[test]
     fn test_parse_instructions() {
-        assert_eq!(parse_instruction("nop +0"), Instruction::Nop(0));
-        assert_eq!(parse_instruction("acc -3"), Instruction::Acc(-3));
-        assert_eq!(parse_instruction("jmp +2"), Instruction::Jmp(2));
+        assert_eq!(parse_instruction("nop +0"), Ok(Instruction::Nop(0)));
+        assert_eq!(parse_instruction("acc -3"), Ok(Instruction::Acc(-3)));
+        assert_eq!(parse_instruction("jmp +2"), Ok(Instruction::Jmp(2)));
+    }
+
+    #[test]
+    fn test_parse_extended_instructions() {
+        let a = Register::parse("a").unwrap();
+        assert_eq!(
+            parse_instruction("set a acc"),
+            Ok(Instruction::Set(a, Operand::Register(Register::ACC)))
+        );
+        assert_eq!(
+            parse_instruction("jnz a -2"),
+            Ok(Instruction::Jnz(Operand::Register(a), -2))
+        );
+        assert_eq!(
+            parse_instruction("out 7"),
+            Ok(Instruction::Out(Operand::Value(7)))
+        );
+        assert_eq!(parse_instruction("hlt"), Ok(Instruction::Hlt));
+        assert!(parse_instruction("set 3 a").is_err());
+        assert!(parse_instruction("jmp").is_err());
+        assert!(parse_instruction("jmp x").is_err());
+
+        assert_eq!(
+            Program::compile("nop +0\nacc +1\nfoo +2"),
+            Err(ParseError {
+                line: 3,
+                message: "unknown opcode \"foo\"".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_registers_and_output() {
+        // 5! by repeated multiplication
+        let instructions = Program::compile(
+            "set a 5\n\
+                           set acc 1\n\
+                           mul acc a\n\
+                           sub a 1\n\
+                           out acc\n\
+                           jz a +2\n\
+                           jmp -4\n\
+                           hlt\n\
+                           out 99",
+        )
+        .unwrap();
+        let mut program = Program::with_instructions(&instructions);
+        assert_eq!(program.run(), Outcome::Looped);
+
+        let mut program =
+            Program::with_instructions(&instructions).with_loop_check(LoopCheck::State);
+        assert_eq!(program.run(), Outcome::Terminated);
+        assert_eq!(program.cpu.acc(), 120);
+        assert_eq!(program.cpu.output, vec![5, 20, 60, 120, 120]);
+        assert!(program.halted);
+    }
+
+    #[test]
+    fn test_infinite_loop() {
+        let instructions = Program::compile(
+            "nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6",
+        )
+        .unwrap();
+        let mut program = Program::with_instructions(&instructions);
+        assert_eq!(program.run(), Outcome::Looped);
+        assert_eq!(program.cpu.acc(), 5);
+
+        let instructions = Program::compile("set a 3\nset a 3\njmp -1").unwrap();
+        let mut program =
+            Program::with_instructions(&instructions).with_loop_check(LoopCheck::State);
+        assert_eq!(program.run(), Outcome::Looped);
+        assert_eq!(program.pointer, 1);
+
+        let instructions = Program::compile("set a 1\ndiv a 0").unwrap();
+        let mut program = Program::with_instructions(&instructions);
+        assert_eq!(
+            program.run(),
+            Outcome::Failed("Division by zero at 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        let run = |source: &str| {
+            let instructions = Program::compile(source).unwrap();
+            let mut program = Program::with_instructions(&instructions);
+            while !program.is_finished() {
+                program.step()?;
+            }
+            Ok(program.cpu.acc())
+        };
+        assert_eq!(
+            run("set a 4611686018427387904\nmul a 4"),
+            Err("Overflow at 1".to_string())
+        );
+        assert_eq!(
+            run("set a -9223372036854775808\ndiv a -1"),
+            Err("Overflow at 1".to_string())
+        );
+        assert_eq!(
+            run("set a -9223372036854775808\nsub a 1"),
+            Err("Overflow at 1".to_string())
+        );
+        assert_eq!(
+            run("set acc 9223372036854775807\nadd acc 1"),
+            Err("Overflow at 1".to_string())
+        );
+        assert_eq!(
+            run("acc +9223372036854775807\nacc +1"),
+            Err("Overflow at 1".to_string())
+        );
+        assert_eq!(
+            run("nop +0\njmp +9223372036854775807"),
+            Err("Overflow at 1".to_string())
+        );
+        assert_eq!(run("acc +2\njmp -5\nacc +1"), Ok(2));
+
+        let far = Program::compile("nop +0\njmp +9223372036854775807").unwrap();
+        assert_eq!(jump_target(far.len(), 1, isize::MAX), far.len());
+        let mut out = vec![];
+        disassemble(&far, &mut out).unwrap();
+        assert!(String::from_utf8(out).unwrap().contains("; -> end"));
+        assert_eq!(
+            run("set a -9223372036854775807\ndiv a -1\nset acc a"),
+            Ok(isize::MAX)
+        );
+    }
+
+    #[derive(Debug)]
+    struct Double(Register);
+
+    impl Custom for Double {
+        fn name(&self) -> &str {
+            "dbl"
+        }
+
+        fn args(&self) -> Vec<String> {
+            vec![self.0.to_string()]
+        }
+
+        fn execute(&self, cpu: &mut Cpu) -> Result<Flow, String> {
+            cpu.set(self.0, cpu.get(self.0) * 2);
+            Ok(Flow::Next)
+        }
+    }
+
+    fn parse_double(args: &[&str]) -> Result<Rc<dyn Custom>, String> {
+        match args {
+            [reg] => Ok(Rc::new(Double(Register::parse(reg)?))),
+            _ => Err("dbl takes one register".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_custom_instruction() {
+        assert!(Program::compile("dbl acc").is_err());
+
+        let parser = Parser::new().with_instruction("dbl", parse_double);
+        let instructions = parser.compile("acc +3\ndbl acc\ndbl acc").unwrap();
+        assert_eq!(instructions[1].to_string(), "dbl acc");
+        assert_eq!(instructions[1], instructions[1].clone());
+        assert_ne!(instructions[1], instructions[2]);
+        let mut program = Program::with_instructions(&instructions);
+        assert_eq!(program.run(), Outcome::Terminated);
+        assert_eq!(program.cpu.acc(), 12);
+
+        assert_eq!(
+            parser.compile("acc +3\ndbl").unwrap_err().to_string(),
+            "line 2: dbl takes one register"
+        );
+    }
+
+    fn debug(source: &str, commands: &str) -> String {
+        let instructions = Program::compile(source).unwrap();
+        let mut debugger = Debugger::new(Program::with_instructions(&instructions));
+        let mut out = vec![];
+        debugger.run(commands.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_debugger() {
+        let source = "nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6";
+        let out = debug(
+            source,
+            "step 2\nbreak 6\ncontinue\nprint\nloop\nvisited\nhistory 2\nquit",
+        );
+        assert_eq!(
+            out,
+            "   0: nop +0       acc=0\n\
+             (dbg)    2: jmp +4       acc=1\n\
+             (dbg) breakpoint set at 6\n\
+             (dbg) breakpoint at 6\n\
+             \x20  6: acc +1       acc=1\n\
+             (dbg)    6: acc +1       acc=1\n\
+             (dbg) Already run 1\n\
+             \x20  1: acc +1       acc=5\n\
+             (dbg) visited: 0 1 2 3 4 6 7\n\
+             (dbg)    3: acc +3       acc=5\n\
+             \x20  4: jmp -3       acc=5\n\
+             (dbg) "
+        );
+    }
+
+    #[test]
+    fn test_debugger_step_zero() {
+        let out = debug("acc +1\njmp -1", "step 0\nhistory\nstep\nhistory");
+        assert_eq!(
+            out,
+            "   0: acc +1       acc=0\n\
+             (dbg)    0: acc +1       acc=0\n\
+             (dbg) (dbg)    1: jmp -1       acc=1\n\
+             (dbg)    0: acc +1       acc=1\n\
+             (dbg) \n"
+        );
+    }
+
+    #[test]
+    fn test_history_only_when_debugging() {
+        let instructions = Program::compile("acc +1\njmp -1").unwrap();
+        let mut program = Program::with_instructions(&instructions);
+        assert_eq!(program.run(), Outcome::Looped);
+        assert!(program.history.is_empty());
+
+        let mut program = Program::with_instructions(&instructions).with_history();
+        assert_eq!(program.run(), Outcome::Looped);
+        assert_eq!(program.history, vec![(0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn test_debugger_reset_and_end() {
+        let out = debug("set a 2\nacc +1\nhlt", "b 9\nd 9\nd 9\np\nc\ns\nr\nwat");
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines[1], "(dbg) breakpoint set at 9");
+        assert_eq!(lines[2], "(dbg) breakpoint removed at 9");
+        assert_eq!(lines[3], "(dbg) no breakpoint at 9");
+        assert_eq!(lines[5], "(dbg) terminated, acc=1");
+        assert_eq!(lines[6], "(dbg) Program has terminated");
+        assert_eq!(lines[8], "(dbg)    0: set a +2     acc=0");
+        assert_eq!(
+            lines[9],
+            "(dbg) break N      stop before running instruction N"
+        );
+    }
+
+    #[test]
+    fn test_repair() {
+        let instructions = Program::compile(
+            "nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6",
+        )
+        .unwrap();
+        assert_eq!(
+            repair(&instructions),
+            Ok(Some(Repair {
+                index: 7,
+                flipped: Instruction::Nop(-4),
+                acc: 8,
+                unique: true,
+            }))
+        );
+
+        // Either jump can be skipped to reach the end
+        let instructions = Program::compile("nop +5\njmp -1").unwrap();
+        let fix = repair(&instructions).unwrap().unwrap();
+        assert_eq!(fix.index, 0);
+        assert!(!fix.unique);
+
+        let instructions = Program::compile("acc +1\nhlt").unwrap();
+        assert_eq!(repair(&instructions), Ok(None));
+
+        let instructions = Program::compile("nop +0\njmp -1\nacc +1\njmp -1").unwrap();
+        assert_eq!(repair(&instructions), Ok(None));
+
+        let instructions = Program::compile("jnz acc +2\njmp -1").unwrap();
+        assert!(repair(&instructions).is_err());
+
+        let instructions = Program::compile("set a 8\ndiv a 2\njmp -1\nout a").unwrap();
+        assert_eq!(
+            repair(&instructions),
+            Ok(Some(Repair {
+                index: 2,
+                flipped: Instruction::Nop(-1),
+                acc: 0,
+                unique: true,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_repair_matches_brute_force() {
+        let input = std::fs::read_to_string("input.txt").unwrap();
+        let instructions = Program::compile(&input).unwrap();
+        let fix = repair(&instructions).unwrap().unwrap();
+
+        let mut working = vec![];
+        for i in 0..instructions.len() {
+            let mut patched = instructions.clone();
+            patched[i] = match instructions[i] {
+                Instruction::Jmp(val) => Instruction::Nop(val),
+                Instruction::Nop(val) => Instruction::Jmp(val),
+                _ => continue,
+            };
+            let mut program = Program::with_instructions(&patched);
+            if program.run() == Outcome::Terminated {
+                working.push((i, program.cpu.acc()));
+            }
+        }
+        assert_eq!(working, vec![(fix.index, fix.acc)]);
+        assert!(fix.unique);
+    }
+
+    #[test]
+    fn test_assemble_round_trip() {
+        let input = std::fs::read_to_string("input.txt").unwrap();
+        let instructions = Program::compile(&input).unwrap();
+        assert_eq!(assemble(&instructions), input);
+
+        let source = "set a -5\nadd acc a\njz b +2\nout acc\nhlt\n";
+        let instructions = Program::compile(source).unwrap();
+        assert_eq!(assemble(&instructions), source);
+        assert_eq!(Program::compile(&assemble(&instructions)), Ok(instructions));
+
+        let instructions = Program::compile("set a +2\nadd a +2").unwrap();
+        assert_ne!(instructions[0], instructions[1]);
+        assert_ne!(
+            Instruction::Jz(Operand::Value(0), 1),
+            Instruction::Jnz(Operand::Value(0), 1)
+        );
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let instructions = Program::compile(
+            "nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6",
+        )
+        .unwrap();
+        let mut out = vec![];
+        disassemble(&instructions, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "L0:\n\
+             \x20    0  nop +0\n\
+             \n\
+             L1:                ; from 4\n\
+             \x20    1  acc +1\n\
+             \x20    2  jmp +4          ; -> L6\n\
+             \n\
+             L3:                ; from 7\n\
+             \x20    3  acc +3\n\
+             \x20    4  jmp -3          ; -> L1\n\
+             \n\
+             L5:\n\
+             \x20    5  acc -99\n\
+             \n\
+             L6:                ; from 2\n\
+             \x20    6  acc +1\n\
+             \x20    7  jmp -4          ; -> L3\n\
+             \n\
+             L8:\n\
+             \x20    8  acc +6\n"
+        );
+    }
+
+    #[test]
+    fn test_trace() {
+        let instructions = Program::compile("nop +0\nacc +1\njmp -1").unwrap();
+        let mut program = Program::with_instructions(&instructions);
+        let mut out = vec![];
+        assert!(!trace(&mut program, &mut out).unwrap());
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "   0: nop +0       acc=0\n\
+             \x20  1: acc +1       acc=1\n\
+             \x20  2: jmp -1       acc=1\n\
+             Already run 1\n"
+        );
+
+        let instructions = Program::compile("acc +2\njmp +2\nacc +9").unwrap();
+        let mut program = Program::with_instructions(&instructions);
+        let mut out = vec![];
+        assert!(trace(&mut program, &mut out).unwrap());
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("jmp +2       acc=2\nterminated, acc=2\n"));
     }
 }
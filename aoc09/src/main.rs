@@ -1,37 +1,365 @@
-use std::cmp::Ordering;
-
-fn main() {
-    let input = std::fs::read_to_string("input.txt").unwrap();
-    let numbers: Vec<_> = input.lines().map(|s| s.parse::<isize>().unwrap()).collect();
-    let (idx, target) = numbers.iter().enumerate().skip(25).find(|&(i, target)| {
-        let previous = &numbers[(i - 25)..i];
-        let mut okay = false;
-        for (j, num) in previous.iter().enumerate() {
-            let difference = target - num;
-            if let Some(_) = previous[j+1..].iter().find(|&n| *n == difference) {
-                okay = true;
-                break;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+/// The last `size` numbers seen, along with every sum of two of them, so
+/// checking a new number costs a single lookup.
+#[derive(Debug)]
+pub struct Window {
+    size: usize,
+    numbers: VecDeque<isize>,
+    sums: HashMap<isize, usize>,
+}
+
+impl Window {
+    pub fn new(size: usize) -> Self {
+        Window {
+            size,
+            numbers: VecDeque::with_capacity(size + 1),
+            sums: HashMap::new(),
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.numbers.len() >= self.size
+    }
+
+    /// Whether two numbers at different positions in the window add up to
+    /// `target`. They may have the same value.
+    pub fn is_valid(&self, target: isize) -> bool {
+        self.sums.contains_key(&target)
+    }
+
+    /// Adds a number, dropping the oldest once the window is full.
+    pub fn push(&mut self, number: isize) {
+        if self.is_full() {
+            if let Some(oldest) = self.numbers.pop_front() {
+                // Sums that overflow were never stored, and can't be a target
+                for sum in self.numbers.iter().filter_map(|&n| oldest.checked_add(n)) {
+                    if let Some(count) = self.sums.get_mut(&sum) {
+                        *count -= 1;
+                        // Drop spent sums so the map only covers the window
+                        if *count == 0 {
+                            self.sums.remove(&sum);
+                        }
+                    }
+                }
+            }
+        }
+        for sum in self.numbers.iter().filter_map(|&n| number.checked_add(n)) {
+            *self.sums.entry(sum).or_insert(0) += 1;
+        }
+        self.numbers.push_back(number);
+    }
+}
+
+/// The first number after the preamble that isn't the sum of two of the
+/// `preamble` numbers before it, with its index.
+pub fn first_invalid(numbers: &[isize], preamble: usize) -> Option<(usize, isize)> {
+    let mut window = Window::new(preamble);
+    for (i, &number) in numbers.iter().enumerate() {
+        if window.is_full() && !window.is_valid(number) {
+            return Some((i, number));
+        }
+        window.push(number);
+    }
+    None
+}
+
+/// Reads one number per line, writing each invalid one with its index as soon
+/// as it arrives. Only the window is kept, so the input can be any length.
+/// Returns how many invalid numbers were found.
+pub fn stream_invalid<R: BufRead, W: Write>(
+    input: R,
+    preamble: usize,
+    out: &mut W,
+) -> io::Result<usize> {
+    let mut window = Window::new(preamble);
+    let mut invalid = 0;
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        let number = line.trim().parse::<isize>().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {:?}: {}", i + 1, line, e),
+            )
+        })?;
+        if window.is_full() && !window.is_valid(number) {
+            writeln!(out, "Invalid: {} (at {})", number, i)?;
+            out.flush()?;
+            invalid += 1;
+        }
+        window.push(number);
+    }
+    Ok(invalid)
+}
+
+/// A run of at least two contiguous numbers that add up to some target.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Run {
+    pub range: Range<usize>,
+    pub min: isize,
+    pub max: isize,
+}
+
+impl Run {
+    fn new(numbers: &[isize], range: Range<usize>) -> Self {
+        let slice = &numbers[range.clone()];
+        Run {
+            min: slice.iter().copied().min().unwrap_or_default(),
+            max: slice.iter().copied().max().unwrap_or_default(),
+            range,
+        }
+    }
+}
+
+/// Every range of two or more numbers adding up to `target`, ordered by
+/// start then end.
+///
+/// With only positive numbers, a window that grows at the back and shrinks
+/// at the front finds them in one pass. Otherwise, a range `i..j` matches
+/// when `prefix[j] - prefix[i] == target`, so each end only needs a lookup
+/// of the prefix sums seen so far. Sums are kept as `i128` so no input
+/// can overflow them.
+pub fn contiguous_ranges(numbers: &[isize], target: isize) -> Vec<Run> {
+    let mut ranges = vec![];
+    let target = target as i128;
+    if numbers.iter().all(|&n| n > 0) {
+        let (mut start, mut sum) = (0, 0);
+        for (end, &number) in numbers.iter().enumerate() {
+            sum += number as i128;
+            while sum > target && start < end {
+                sum -= numbers[start] as i128;
+                start += 1;
+            }
+            if sum == target && end > start {
+                ranges.push(start..end + 1);
+            }
+        }
+    } else {
+        let mut prefix = vec![0i128];
+        for &number in numbers {
+            prefix.push(prefix[prefix.len() - 1] + number as i128);
+        }
+        let mut starts: HashMap<i128, Vec<usize>> = HashMap::new();
+        for end in 2..prefix.len() {
+            // Only prefixes at least two numbers back can start a run here
+            starts.entry(prefix[end - 2]).or_default().push(end - 2);
+            if let Some(found) = starts.get(&(prefix[end] - target)) {
+                ranges.extend(found.iter().map(|&start| start..end));
+            }
+        }
+        ranges.sort_by_key(|range| (range.start, range.end));
+    }
+    ranges
+        .into_iter()
+        .map(|range| Run::new(numbers, range))
+        .collect()
+}
+
+/// The sum of the smallest and largest numbers in the contiguous range that
+/// adds up to the first invalid number.
+pub fn encryption_weakness(numbers: &[isize], preamble: usize) -> Option<isize> {
+    let (_, target) = first_invalid(numbers, preamble)?;
+    let run = contiguous_ranges(numbers, target).into_iter().next()?;
+    Some(run.min + run.max)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut input_path = "input.txt".to_string();
+    let mut preamble = 25;
+    let mut all = false;
+    let mut stream = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--preamble" => {
+                preamble = args.next().ok_or("--preamble needs a length")?.parse()?;
+                if preamble == 0 {
+                    return Err("--preamble must be at least 1".into());
+                }
             }
+            "--all" => all = true,
+            "--stream" => stream = true,
+            _ => input_path = arg,
+        }
+    }
+
+    if stream {
+        let stdout = io::stdout();
+        let invalid = stream_invalid(io::stdin().lock(), preamble, &mut stdout.lock())?;
+        eprintln!("{} invalid numbers", invalid);
+        return Ok(());
+    }
+
+    let input = std::fs::read_to_string(input_path)?;
+    let numbers = input
+        .lines()
+        .map(|s| s.parse::<isize>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (idx, target) = first_invalid(&numbers, preamble).ok_or("No invalid number")?;
+    println!("Target: {:?} (at {})", target, idx);
+    if all {
+        for run in contiguous_ranges(&numbers, target) {
+            println!(
+                "Range {}..{}: min {}, max {}, weakness {}",
+                run.range.start,
+                run.range.end,
+                run.min,
+                run.max,
+                run.min + run.max
+            );
+        }
+    }
+    match encryption_weakness(&numbers, preamble) {
+        Some(weakness) => println!("Weakness: {}", weakness),
+        None => println!("No contiguous range adds up to {}", target),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [isize; 20] = [
+        35, 20, 15, 25, 47, 40, 62, 55, 65, 95, 102, 117, 150, 182, 127, 219, 299, 277, 309, 576,
+    ];
+
+    #[test]
+    fn test_first_invalid() {
+        assert_eq!(first_invalid(&EXAMPLE, 5), Some((14, 127)));
+        assert_eq!(first_invalid(&EXAMPLE[..14], 5), None);
+    }
+
+    #[test]
+    fn test_encryption_weakness() {
+        assert_eq!(
+            contiguous_ranges(&EXAMPLE, 127),
+            vec![Run {
+                range: 2..6,
+                min: 15,
+                max: 47
+            }]
+        );
+        assert_eq!(encryption_weakness(&EXAMPLE, 5), Some(62));
+    }
+
+    #[test]
+    fn test_window() {
+        let mut window = Window::new(3);
+        for n in &[1, 2, 3] {
+            window.push(*n);
+        }
+        assert!(window.is_full());
+        assert!(window.is_valid(5));
+        assert!(!window.is_valid(6));
+        assert!(!window.is_valid(2));
+
+        // Equal values at different positions still count as a pair
+        window.push(3);
+        assert!(!window.is_valid(3));
+        assert!(window.is_valid(5));
+        assert!(window.is_valid(6));
+
+        window.push(3);
+        assert!(!window.is_valid(5));
+        assert!(window.is_valid(6));
+        assert_eq!(first_invalid(&[2, 3, 3, 6, 7], 3), Some((4, 7)));
+    }
+
+    #[test]
+    fn test_stream_invalid() {
+        let input = EXAMPLE
+            .iter()
+            .chain(&[885, 1000])
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut out = vec![];
+        assert_eq!(stream_invalid(input.as_bytes(), 5, &mut out).unwrap(), 2);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Invalid: 127 (at 14)\nInvalid: 1000 (at 21)\n"
+        );
+
+        let error = stream_invalid("1\n2\nthree\n".as_bytes(), 2, &mut vec![]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("line 3:"));
+    }
+
+    #[test]
+    fn test_window_memory() {
+        let mut window = Window::new(5);
+        for n in 0..100_000 {
+            window.push(n * 7 % 1013);
+            assert!(window.sums.len() <= 10);
         }
-        !okay
-    }).unwrap();
-
-    println!("Target: {:?}", target);
-    for i in 0..idx {
-        let mut sum = 0;
-        for (j, num) in numbers[i..idx].iter().enumerate() {
-            sum += num;
-            match target.cmp(&sum) {
-                Ordering::Equal => {
-                    let slice = &numbers[i..=(j+i)];
-                    let min = slice.iter().min().unwrap();
-                    let max = slice.iter().max().unwrap();
-                    println!("Found: {} + {} = {}", min, max, min + max);
-                    return;
-                },
-                Ordering::Less => break,
-                Ordering::Greater => continue
+        assert_eq!(window.numbers.len(), 5);
+    }
+
+    #[test]
+    fn test_window_overflow() {
+        let mut window = Window::new(3);
+        for &n in &[isize::MAX, 1, 5, isize::MIN, -1] {
+            window.push(n);
+        }
+        assert!(window.is_valid(isize::MIN + 5));
+        assert!(window.is_valid(4));
+        assert!(!window.is_valid(isize::MAX));
+        assert_eq!(window.sums.len(), 2);
+        assert_eq!(first_invalid(&[isize::MAX, 1, 5, 6], 2), Some((2, 5)));
+    }
+
+    fn brute_force(numbers: &[isize], target: isize) -> Vec<Range<usize>> {
+        let mut ranges = vec![];
+        for start in 0..numbers.len() {
+            for end in start + 2..=numbers.len() {
+                if numbers[start..end].iter().sum::<isize>() == target {
+                    ranges.push(start..end);
+                }
             }
         }
+        ranges
+    }
+
+    #[test]
+    fn test_contiguous_ranges() {
+        let ranges = |numbers: &[isize], target| {
+            contiguous_ranges(numbers, target)
+                .into_iter()
+                .map(|run| run.range)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ranges(&[1, 2, 3, 3, 2, 1], 5), vec![1..3, 3..5]);
+        assert_eq!(ranges(&[5, 1, 4], 5), vec![1..3]);
+        assert_eq!(
+            ranges(&[3, -1, 2, 0, 4, -4, 1], 4),
+            vec![0..3, 0..4, 0..6, 3..5]
+        );
+        assert_eq!(ranges(&[0, 0, 0], 0), vec![0..2, 0..3, 1..3]);
+        assert_eq!(ranges(&[isize::MAX, isize::MAX, 1, 2], 3), vec![2..4]);
+        assert_eq!(
+            ranges(&[isize::MAX, 1, isize::MIN, -1], isize::MIN),
+            vec![1..4]
+        );
+        assert_eq!(
+            contiguous_ranges(&[3, -1, 2], 4),
+            vec![Run {
+                range: 0..3,
+                min: -1,
+                max: 3
+            }]
+        );
+
+        let numbers = [4, -2, 7, 0, -5, 3, 3, -1, 6, -6, 2, 2, 0, 1];
+        for target in -6..12 {
+            assert_eq!(ranges(&numbers, target), brute_force(&numbers, target));
+        }
+        let positive = [3, 1, 4, 1, 5, 9, 2, 6, 5, 3, 5];
+        for target in 0..30 {
+            assert_eq!(ranges(&positive, target), brute_force(&positive, target));
+        }
     }
 }
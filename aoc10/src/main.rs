@@ -1,73 +1,383 @@
-use itertools::Itertools;
+use std::collections::BTreeMap;
+use std::fmt;
 
-// really this is off by one from the "canonical" trib sequence, but
-// more convenient for this problem.
-fn tribonacci(n: usize) -> usize {
-    if n < 2 {
-        return 1;
+/// How adapters connect: the outlet's rating, how far above the highest
+/// adapter the device sits, and the largest step up an adapter can take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rules {
+    outlet: u16,
+    device_offset: u16,
+    max_step: u16,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            outlet: 0,
+            device_offset: 3,
+            max_step: 3,
+        }
     }
+}
 
-    let mut a = 0;
-    let mut b = 1;
-    let mut c = 1;
+/// Two neighbouring joltages in the full chain that can't be connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ChainError {
+    from: u16,
+    to: u16,
+}
 
-    let mut curr = 0;
-    for _ in 1..n {
-        curr = a + b + c;
-        a = b;
-        b = c;
-        c = curr;
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "can't connect {} jolts to {} jolts", self.from, self.to)
     }
-    curr
 }
 
-fn sort_adapters(adapters: &mut Vec<u16>) {
-    // Add outlet
-    adapters.push(0);
+impl std::error::Error for ChainError {}
+
+/// Sorts the adapters between the outlet and the device, checking that every
+/// adapter can be used in one chain.
+fn sort_adapters(adapters: &mut Vec<u16>, rules: &Rules) -> Result<(), ChainError> {
     adapters.sort_unstable();
+    // Add outlet
+    adapters.insert(0, rules.outlet);
     // Add device
-    adapters.push(adapters.last().unwrap() + 3);
+    let highest = adapters[adapters.len() - 1];
+    adapters.push(highest.saturating_add(rules.device_offset));
+    match adapters
+        .windows(2)
+        .find(|s| s[1] <= s[0] || s[1] - s[0] > rules.max_step)
+    {
+        Some(s) => Err(ChainError {
+            from: s[0],
+            to: s[1],
+        }),
+        None => Ok(()),
+    }
 }
 
-fn jolt_diff_summary(adapters: &[u16]) -> (u16, u16, u16) {
-    let mut ones = 0u16;
-    let mut twos = 0u16;
-    let mut threes = 0u16;
+/// How many times each difference occurs between neighbouring adapters.
+fn jolt_diff_summary(adapters: &[u16]) -> BTreeMap<u16, usize> {
+    let mut histogram = BTreeMap::new();
     for diff in adapters.windows(2).map(|s| s[1] - s[0]) {
-        match diff {
-            1 => ones += 1,
-            2 => twos += 1,
-            3 => threes += 1,
-            _ => (),
+        *histogram.entry(diff).or_insert(0) += 1;
+    }
+    histogram
+}
+
+/// For each adapter in a sorted chain, how many ways there are to reach it
+/// from the first one, stepping up at most `max_step` jolts at a time. `None`
+/// if a count doesn't fit in a `u128`.
+fn paths_to(adapters: &[u16], max_step: u16) -> Option<Vec<u128>> {
+    let mut ways = vec![0u128; adapters.len()];
+    for i in 0..adapters.len() {
+        if i == 0 {
+            ways[i] = 1;
+            continue;
+        }
+        let mut total = 0u128;
+        for j in (0..i).rev() {
+            if adapters[i] - adapters[j] > max_step {
+                break;
+            }
+            total = total.checked_add(ways[j])?;
         }
+        ways[i] = total;
     }
-    (ones, twos, threes)
+    Some(ways)
 }
 
-fn arrangements(adapters: &[u16]) -> usize {
-    let mut possibilities = 1;
-    for (ones, group) in &adapters
-        .windows(2)
-        .map(|s| s[1] - s[0])
-        .group_by(|&n| n == 1)
-    {
-        if ones {
-            possibilities *= tribonacci(group.count());
+/// For each adapter in a sorted chain, how many ways there are to get from it
+/// to the last one.
+fn paths_from(adapters: &[u16], max_step: u16) -> Option<Vec<u128>> {
+    let last = adapters.len().checked_sub(1);
+    let mut ways = vec![0u128; adapters.len()];
+    for i in (0..adapters.len()).rev() {
+        if Some(i) == last {
+            ways[i] = 1;
+            continue;
+        }
+        let mut total = 0u128;
+        for j in i + 1..adapters.len() {
+            if adapters[j] - adapters[i] > max_step {
+                break;
+            }
+            total = total.checked_add(ways[j])?;
+        }
+        ways[i] = total;
+    }
+    Some(ways)
+}
+
+/// How many ways there are to get from the first adapter to the last one in
+/// a sorted chain, stepping up at most `max_step` jolts at a time. `None` if
+/// the count doesn't fit in a `u128`.
+fn arrangements(adapters: &[u16], max_step: u16) -> Option<u128> {
+    Some(paths_to(adapters, max_step)?.last().copied().unwrap_or(0))
+}
+
+/// Every valid chain through a sorted list of adapters, one at a time, in
+/// lexicographic order of the adapters used.
+struct Chains<'a> {
+    adapters: &'a [u16],
+    max_step: u16,
+    /// Whether the last adapter can be reached from each one, so the search
+    /// never walks into a dead end.
+    reaches_end: Vec<bool>,
+    path: Vec<usize>,
+    started: bool,
+}
+
+fn chains(adapters: &[u16], max_step: u16) -> Chains<'_> {
+    let mut reaches_end = vec![false; adapters.len()];
+    for i in (0..adapters.len()).rev() {
+        reaches_end[i] = i + 1 == adapters.len()
+            || (i + 1..adapters.len())
+                .take_while(|&j| adapters[j] - adapters[i] <= max_step)
+                .any(|j| reaches_end[j]);
+    }
+    Chains {
+        adapters,
+        max_step,
+        reaches_end,
+        path: vec![],
+        started: false,
+    }
+}
+
+impl Chains<'_> {
+    /// The first adapter after `after` that can follow `from` and still
+    /// reach the end.
+    fn next_step(&self, from: usize, after: usize) -> Option<usize> {
+        (after + 1..self.adapters.len())
+            .take_while(|&j| self.adapters[j] - self.adapters[from] <= self.max_step)
+            .find(|&j| self.reaches_end[j])
+    }
+}
+
+impl Iterator for Chains<'_> {
+    type Item = Vec<u16>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            if !self.reaches_end.first().copied().unwrap_or(false) {
+                return None;
+            }
+            self.path.push(0);
+        } else {
+            // Swap the deepest adapter that has an alternative for the next one
+            loop {
+                let last = self.path.pop()?;
+                let &from = self.path.last()?;
+                if let Some(next) = self.next_step(from, last) {
+                    self.path.push(next);
+                    break;
+                }
+            }
+        }
+        while let Some(&last) = self.path.last() {
+            if last + 1 == self.adapters.len() {
+                break;
+            }
+            let next = self.next_step(last, last)?;
+            self.path.push(next);
+        }
+        Some(self.path.iter().map(|&i| self.adapters[i]).collect())
+    }
+}
+
+/// A small seedable pseudo-random generator (SplitMix64).
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`, without modulo bias.
+    fn below(&mut self, bound: u128) -> u128 {
+        let zone = u128::MAX - u128::MAX % bound;
+        loop {
+            let value = (self.next_u64() as u128) << 64 | self.next_u64() as u128;
+            if value < zone {
+                return value % bound;
+            }
         }
     }
-    possibilities
+}
+
+/// A chain picked uniformly at random from every valid one. `None` if there
+/// are none, or too many to count.
+fn sample_chain(adapters: &[u16], max_step: u16, rng: &mut Rng) -> Option<Vec<u16>> {
+    let ways = paths_from(adapters, max_step)?;
+    if ways.first().copied().unwrap_or(0) == 0 {
+        return None;
+    }
+    let mut i = 0;
+    let mut chain = vec![adapters[0]];
+    while i + 1 < adapters.len() {
+        // Each next adapter is weighted by how many chains continue from it
+        let mut pick = rng.below(ways[i]);
+        i = (i + 1..adapters.len()).find(|&j| {
+            if pick < ways[j] {
+                true
+            } else {
+                pick -= ways[j];
+                false
+            }
+        })?;
+        chain.push(adapters[i]);
+    }
+    Some(chain)
+}
+
+/// A chain using as few adapters as possible.
+fn shortest_chain(adapters: &[u16], max_step: u16) -> Option<Vec<u16>> {
+    let mut i = 0;
+    let mut chain = vec![*adapters.first()?];
+    while i + 1 < adapters.len() {
+        // Jumping as far as possible never leaves fewer options later
+        let next = (i + 1..adapters.len())
+            .take_while(|&j| adapters[j] - adapters[i] <= max_step)
+            .last()?;
+        chain.push(adapters[next]);
+        i = next;
+    }
+    Some(chain)
+}
+
+/// How many valid chains include an adapter rated `joltage`.
+fn chains_through(adapters: &[u16], max_step: u16, joltage: u16) -> Option<u128> {
+    let to = paths_to(adapters, max_step)?;
+    let from = paths_from(adapters, max_step)?;
+    let mut total = 0u128;
+    for (i, _) in adapters.iter().enumerate().filter(|(_, &a)| a == joltage) {
+        total = total.checked_add(to[i].checked_mul(from[i])?)?;
+    }
+    Some(total)
+}
+
+/// The adapters every valid chain has to use, or `None` if there is no chain.
+fn mandatory_adapters(adapters: &[u16], max_step: u16) -> Option<Vec<u16>> {
+    shortest_chain(adapters, max_step)?;
+    let last = adapters.len() - 1;
+    Some(
+        (0..adapters.len())
+            .filter(|&i| i == 0 || i == last || adapters[i + 1] - adapters[i - 1] > max_step)
+            .map(|i| adapters[i])
+            .collect(),
+    )
+}
+
+fn format_chain(chain: &[u16]) -> String {
+    chain
+        .iter()
+        .map(|a| a.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn number<T: std::str::FromStr>(
+    args: &mut impl Iterator<Item = String>,
+    flag: &str,
+) -> Result<T, String> {
+    match args.next().map(|n| n.parse()) {
+        Some(Ok(n)) => Ok(n),
+        _ => Err(format!("Error: {} needs a number", flag)),
+    }
 }
 
 fn main() {
+    let mut rules = Rules::default();
+    let mut list = None;
+    let mut seed = None;
+    let mut through = None;
+    let mut shortest = false;
+    let mut mandatory = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "--outlet" => number(&mut args, &arg).map(|n| rules.outlet = n),
+            "--device-offset" => number(&mut args, &arg).map(|n| rules.device_offset = n),
+            "--max-step" => number(&mut args, &arg).map(|n| rules.max_step = n),
+            "--list" => number(&mut args, &arg).map(|n| list = Some(n)),
+            "--sample" => number(&mut args, &arg).map(|n| seed = Some(n)),
+            "--through" => number(&mut args, &arg).map(|n| through = Some(n)),
+            "--shortest" => {
+                shortest = true;
+                Ok(())
+            }
+            "--mandatory" => {
+                mandatory = true;
+                Ok(())
+            }
+            _ => Err(format!("Error: unknown argument {:?}", arg)),
+        };
+        if let Err(e) = parsed {
+            return eprintln!("{}", e);
+        }
+    }
+    let max_step = rules.max_step;
     let input = std::fs::read_to_string("input.txt").expect("error reading input.txt");
     let mut result: Result<Vec<_>, _> = input.lines().map(|line| line.parse::<u16>()).collect();
     match result {
         Ok(ref mut adapters) => {
-            sort_adapters(adapters);
-            let (ones, twos, threes) = jolt_diff_summary(&adapters);
-            println!("Twos: {}", twos);
+            if let Err(e) = sort_adapters(adapters, &rules) {
+                return eprintln!("Error: {}", e);
+            }
+            let histogram = jolt_diff_summary(adapters);
+            for (diff, count) in &histogram {
+                println!("Differences of {}: {}", diff, count);
+            }
+            let count = |diff| histogram.get(&diff).copied().unwrap_or(0);
+            let (ones, threes) = (count(1), count(3));
             println!("Summary: {} * {} = {}", ones, threes, ones * threes);
-            println!("Possible arrangements: {}", arrangements(&adapters));
+            match arrangements(adapters, max_step) {
+                Some(count) => println!("Possible arrangements: {}", count),
+                None => eprintln!("Error: too many arrangements to count"),
+            }
+            if let Some(count) = list {
+                for chain in chains(adapters, max_step).take(count) {
+                    println!("{}", format_chain(&chain));
+                }
+            }
+            if let Some(seed) = seed {
+                match sample_chain(adapters, max_step, &mut Rng::new(seed)) {
+                    Some(chain) => println!("Sample: {}", format_chain(&chain)),
+                    None => eprintln!("Error: no chain to sample"),
+                }
+            }
+            if shortest {
+                match shortest_chain(adapters, max_step) {
+                    Some(chain) => println!(
+                        "Shortest ({} adapters): {}",
+                        chain.len(),
+                        format_chain(&chain)
+                    ),
+                    None => eprintln!("Error: no chain reaches the device"),
+                }
+            }
+            if let Some(joltage) = through {
+                match chains_through(adapters, max_step, joltage) {
+                    Some(count) => println!("Arrangements through {}: {}", joltage, count),
+                    None => eprintln!("Error: too many arrangements to count"),
+                }
+            }
+            if mandatory {
+                match mandatory_adapters(adapters, max_step) {
+                    Some(required) => println!("Mandatory: {}", format_chain(&required)),
+                    None => eprintln!("Error: no chain reaches the device"),
+                }
+            }
         }
         Err(e) => eprintln!("Error: {}", e),
     }
@@ -83,8 +393,58 @@ mod tests {
             28, 33, 18, 42, 31, 14, 46, 20, 48, 47, 24, 23, 49, 45, 19, 38, 39, 11, 1, 32, 25, 35,
             8, 17, 7, 9, 4, 2, 34, 10, 3,
         ];
-        sort_adapters(&mut adapters);
-        assert_eq!(jolt_diff_summary(&adapters), (22, 0, 10));
+        sort_adapters(&mut adapters, &Rules::default()).unwrap();
+        assert_eq!(
+            jolt_diff_summary(&adapters),
+            vec![(1, 22), (3, 10)].into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn test_rules() {
+        let rules = Rules {
+            outlet: 2,
+            device_offset: 1,
+            max_step: 4,
+        };
+        let mut adapters = vec![10, 3, 6, 7];
+        sort_adapters(&mut adapters, &rules).unwrap();
+        assert_eq!(adapters, vec![2, 3, 6, 7, 10, 11]);
+        assert_eq!(
+            jolt_diff_summary(&adapters),
+            vec![(1, 3), (3, 2)].into_iter().collect()
+        );
+
+        let wide = Rules {
+            max_step: 4,
+            ..Rules::default()
+        };
+        let mut adapters = vec![1, 3, 7];
+        sort_adapters(&mut adapters, &wide).unwrap();
+        assert_eq!(
+            jolt_diff_summary(&adapters),
+            vec![(1, 1), (2, 1), (3, 1), (4, 1)]
+                .into_iter()
+                .collect::<BTreeMap<_, _>>()
+        );
+
+        let mut adapters = vec![10, 4, 6, 7];
+        assert_eq!(
+            sort_adapters(&mut adapters, &Rules::default()),
+            Err(ChainError { from: 0, to: 4 })
+        );
+        assert_eq!(
+            sort_adapters(&mut vec![1, 3, 3], &Rules::default()),
+            Err(ChainError { from: 3, to: 3 })
+        );
+        assert_eq!(
+            sort_adapters(&mut vec![1, 5], &Rules::default()),
+            Err(ChainError { from: 1, to: 5 })
+        );
+        assert_eq!(
+            sort_adapters(&mut vec![1, 3], &rules),
+            Err(ChainError { from: 2, to: 1 })
+        );
     }
 
     #[test]
@@ -93,21 +453,21 @@ mod tests {
             28, 33, 18, 42, 31, 14, 46, 20, 48, 47, 24, 23, 49, 45, 19, 38, 39, 11, 1, 32, 25, 35,
             8, 17, 7, 9, 4, 2, 34, 10, 3,
         ];
-        sort_adapters(&mut adapters);
-        assert_eq!(arrangements(&adapters), 19208);
+        sort_adapters(&mut adapters, &Rules::default()).unwrap();
+        assert_eq!(arrangements(&adapters, 3), Some(19208));
     }
 
     #[test]
     fn test_arrangements_small1() {
         let mut adapters = vec![16, 10, 15, 5, 1, 11, 7, 19, 6, 12, 4];
-        sort_adapters(&mut adapters);
-        assert_eq!(arrangements(&adapters), 8);
+        sort_adapters(&mut adapters, &Rules::default()).unwrap();
+        assert_eq!(arrangements(&adapters, 3), Some(8));
     }
 
     #[test]
     fn test_arrangements_small2() {
         let mut adapters = vec![1, 2, 3, 4];
-        sort_adapters(&mut adapters);
+        sort_adapters(&mut adapters, &Rules::default()).unwrap();
 
         // sequence: 0, 1, 1, 2, 4, 7
 
@@ -132,7 +492,178 @@ mod tests {
         // 0 2 4          -2- -2-
         // 0 3 4          - 3 - 1
 
-        assert_eq!(arrangements(&adapters), 7);
+        assert_eq!(arrangements(&adapters, 3), Some(7));
+    }
+
+    #[test]
+    fn test_arrangements_with_twos() {
+        // 0 1 3 4 7, 0 1 4 7, 0 3 4 7
+        let mut adapters = vec![1, 3, 4];
+        sort_adapters(&mut adapters, &Rules::default()).unwrap();
+        assert_eq!(arrangements(&adapters, 3), Some(3));
+
+        // 0 2 4 6 9; with steps of 4, also 0 2 6 9 and 0 4 6 9
+        let mut adapters = vec![2, 4, 6];
+        sort_adapters(&mut adapters, &Rules::default()).unwrap();
+        assert_eq!(arrangements(&adapters, 3), Some(1));
+        assert_eq!(arrangements(&adapters, 4), Some(3));
+    }
+
+    #[test]
+    fn test_arrangements_max_step() {
+        let adapters = [0, 1, 2, 3, 4, 5];
+        assert_eq!(arrangements(&adapters, 1), Some(1));
+        assert_eq!(arrangements(&adapters, 2), Some(8));
+        assert_eq!(arrangements(&adapters, 5), Some(16));
+        assert_eq!(arrangements(&[], 3), Some(0));
+        assert_eq!(arrangements(&[0, 4], 3), Some(0));
+    }
+
+    #[test]
+    fn test_arrangements_overflow() {
+        let adapters: Vec<u16> = (0..100).collect();
+        assert!(arrangements(&adapters, 3).is_some());
+        let adapters: Vec<u16> = (0..300).collect();
+        assert_eq!(arrangements(&adapters, 3), None);
+    }
+
+    fn small_example() -> Vec<u16> {
+        let mut adapters = vec![16, 10, 15, 5, 1, 11, 7, 19, 6, 12, 4];
+        sort_adapters(&mut adapters, &Rules::default()).unwrap();
+        adapters
+    }
+
+    fn is_valid_chain(adapters: &[u16], chain: &[u16], max_step: u16) -> bool {
+        chain.first() == adapters.first()
+            && chain.last() == adapters.last()
+            && chain
+                .windows(2)
+                .all(|w| w[0] < w[1] && w[1] - w[0] <= max_step)
+            && chain.iter().all(|a| adapters.contains(a))
+    }
+
+    #[test]
+    fn test_chains() {
+        let adapters = small_example();
+        let all: Vec<_> = chains(&adapters, 3).collect();
+        assert_eq!(all.len(), 8);
+        assert_eq!(all[0], vec![0, 1, 4, 5, 6, 7, 10, 11, 12, 15, 16, 19, 22]);
+        assert_eq!(all[7], vec![0, 1, 4, 7, 10, 12, 15, 16, 19, 22]);
+        assert!(all.windows(2).all(|w| w[0] < w[1]));
+        assert!(all.iter().all(|chain| is_valid_chain(&adapters, chain, 3)));
+
+        for (adapters, max_step) in &[
+            (vec![0, 1, 3, 4, 5, 7, 9, 10, 13], 3),
+            (vec![0, 2, 4, 6, 8, 9, 10], 2),
+            (vec![0, 1, 2, 3, 4, 5, 6, 7], 4),
+        ] {
+            let all: Vec<_> = chains(adapters, *max_step).collect();
+            assert_eq!(Some(all.len() as u128), arrangements(adapters, *max_step));
+            assert!(all.windows(2).all(|w| w[0] < w[1]));
+            assert!(all.iter().all(|c| is_valid_chain(adapters, c, *max_step)));
+        }
+
+        // A gap too wide to step over means no chains at all
+        assert_eq!(chains(&[0, 1, 3, 5, 6], 2).count(), 1);
+        assert_eq!(chains(&[0, 1, 5], 3).count(), 0);
+        assert_eq!(chains(&[], 3).count(), 0);
+        assert_eq!(chains(&[0], 3).collect::<Vec<_>>(), vec![vec![0]]);
+    }
+
+    #[test]
+    fn test_sample_chain() {
+        let adapters = small_example();
+        let all: Vec<_> = chains(&adapters, 3).collect();
+        let mut rng = Rng::new(10);
+        let mut counts = vec![0; all.len()];
+        for _ in 0..8000 {
+            let chain = sample_chain(&adapters, 3, &mut rng).unwrap();
+            counts[all.iter().position(|c| *c == chain).unwrap()] += 1;
+        }
+        assert!(
+            counts.iter().all(|&n| (850..1150).contains(&n)),
+            "{:?}",
+            counts
+        );
+
+        let first = sample_chain(&adapters, 3, &mut Rng::new(42));
+        assert_eq!(first, sample_chain(&adapters, 3, &mut Rng::new(42)));
+        assert_eq!(sample_chain(&[0, 1, 5], 3, &mut rng), None);
+    }
+
+    #[test]
+    fn test_shortest_chain() {
+        let adapters = small_example();
+        let shortest = shortest_chain(&adapters, 3).unwrap();
+        let fewest = chains(&adapters, 3).map(|c| c.len()).min();
+        assert_eq!(Some(shortest.len()), fewest);
+        assert!(is_valid_chain(&adapters, &shortest, 3));
+        assert_eq!(
+            shortest_chain(&[0, 1, 2, 3, 4, 5, 6], 2),
+            Some(vec![0, 2, 4, 6])
+        );
+        assert_eq!(shortest_chain(&[0, 1, 5], 3), None);
+    }
+
+    #[test]
+    fn test_chains_through() {
+        let adapters = small_example();
+        for &joltage in &adapters {
+            let expected = chains(&adapters, 3)
+                .filter(|c| c.contains(&joltage))
+                .count() as u128;
+            assert_eq!(chains_through(&adapters, 3, joltage), Some(expected));
+        }
+        assert_eq!(chains_through(&adapters, 3, 2), Some(0));
+    }
+
+    #[test]
+    fn test_mandatory_adapters() {
+        for (adapters, max_step) in &[
+            (small_example(), 3),
+            (vec![0, 1, 3, 4, 5, 7, 9, 10, 13], 3),
+            (vec![0, 1, 1, 2, 5], 3),
+        ] {
+            let all: Vec<_> = chains(adapters, *max_step).collect();
+            let mut expected: Vec<_> = adapters
+                .iter()
+                .copied()
+                .filter(|a| all.iter().all(|c| c.contains(a)))
+                .collect();
+            expected.dedup();
+            assert_eq!(mandatory_adapters(adapters, *max_step), Some(expected));
+        }
+        assert_eq!(mandatory_adapters(&[0, 1, 5], 3), None);
+    }
+
+    // really this is off by one from the "canonical" trib sequence, but
+    // more convenient for this problem.
+    fn tribonacci(n: usize) -> u128 {
+        if n < 2 {
+            return 1;
+        }
+
+        let mut a = 0;
+        let mut b = 1;
+        let mut c = 1;
+
+        let mut curr = 0;
+        for _ in 1..n {
+            curr = a + b + c;
+            a = b;
+            b = c;
+            c = curr;
+        }
+        curr
+    }
+
+    #[test]
+    fn test_runs_of_ones_are_tribonacci() {
+        for n in 0..40u16 {
+            let mut adapters = (1..=n).collect();
+            sort_adapters(&mut adapters, &Rules::default()).unwrap();
+            assert_eq!(arrangements(&adapters, 3), Some(tribonacci(n as usize)));
+        }
     }
 
     #[test]
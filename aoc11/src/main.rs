@@ -1,13 +1,58 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
 use std::str::FromStr;
 
+/// Which spaces around a seat count as its neighbours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Neighbourhood {
+    /// The adjacent spaces above, below, left and right.
+    Four,
+    /// All 8 adjacent spaces, including diagonals.
+    Eight,
+    /// The first seat seen in each of the 8 directions, looking past floor.
+    LineOfSight,
+}
+
+impl FromStr for Neighbourhood {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "4" => Ok(Neighbourhood::Four),
+            "8" => Ok(Neighbourhood::Eight),
+            "sight" => Ok(Neighbourhood::LineOfSight),
+            _ => Err(format!(
+                "unknown neighbourhood {:?}, expected 4, 8 or sight",
+                s
+            )),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct TickSettings {
-    seats_only: bool,
-    occupant_threshold: usize,
+    neighbourhood: Neighbourhood,
+    /// How many spaces away a seat can be seen from, for line of sight.
+    range: Option<usize>,
+    /// An empty seat is taken with at most this many occupied neighbours.
+    occupy_threshold: usize,
+    /// An occupied seat is left with at least this many occupied neighbours.
+    vacate_threshold: usize,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl Default for TickSettings {
+    fn default() -> Self {
+        TickSettings {
+            neighbourhood: Neighbourhood::Eight,
+            range: None,
+            occupy_threshold: 0,
+            vacate_threshold: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum Space {
     Floor,
     EmptySeat,
@@ -24,7 +69,7 @@ impl Space {
         }
     }
 
-    fn to_char(&self) -> char {
+    fn to_char(self) -> char {
         match self {
             Space::Floor => '.',
             Space::EmptySeat => 'L',
@@ -37,15 +82,78 @@ impl Space {
     }
 }
 
+/// Where a simulation settled: the round that first reached a repeating
+/// state, and how many rounds it takes to come back to it. A stable layout
+/// has a cycle length of 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cycle {
+    entry: usize,
+    length: usize,
+}
+
+/// The spaces each space can see, worked out once per map. Floor never
+/// changes, so neither does what's visible.
+#[derive(Debug)]
+struct Visibility {
+    neighbourhood: Neighbourhood,
+    range: Option<usize>,
+    /// `indices[starts[i]..starts[i + 1]]` are the spaces visible from `i`.
+    starts: Vec<usize>,
+    indices: Vec<usize>,
+}
+
+impl Visibility {
+    fn new(map: &Map, settings: &TickSettings) -> Self {
+        let mut starts = Vec::with_capacity(map.spaces.len() + 1);
+        let mut indices = vec![];
+        starts.push(0);
+        for idx in 0..map.spaces.len() {
+            indices.extend(map.look_around(idx, settings));
+            starts.push(indices.len());
+        }
+        Visibility {
+            neighbourhood: settings.neighbourhood,
+            range: settings.range,
+            starts,
+            indices,
+        }
+    }
+
+    fn matches(&self, settings: &TickSettings) -> bool {
+        self.neighbourhood == settings.neighbourhood && self.range == settings.range
+    }
+
+    fn of(&self, idx: usize) -> &[usize] {
+        &self.indices[self.starts[idx]..self.starts[idx + 1]]
+    }
+}
+
 #[derive(Default, Debug)]
 struct Map {
     rows: usize,
     columns: usize,
     spaces: Vec<Space>,
+    /// Scratch space for the next round, swapped with `spaces` on each tick.
+    next: Vec<Space>,
+    visibility: Option<Visibility>,
 }
 
 impl Map {
-    fn visible_seats(&self, idx: usize, seats_only: bool) -> Vec<Space> {
+    #[cfg(test)]
+    fn visible_seats(&self, idx: usize, neighbourhood: Neighbourhood) -> Vec<Space> {
+        let settings = TickSettings {
+            neighbourhood,
+            ..Default::default()
+        };
+        Visibility::new(self, &settings)
+            .of(idx)
+            .iter()
+            .map(|&i| self.spaces[i])
+            .collect()
+    }
+
+    /// Ray-casts in each direction for the first space (or seat) in each.
+    fn look_around(&self, idx: usize, settings: &TickSettings) -> Vec<usize> {
         let mut neighbors = vec![];
         let cols = self.columns as isize;
         let rows = self.rows as isize;
@@ -61,27 +169,34 @@ impl Map {
             }
         };
 
-        for &dir in &[
-            (-1, -1),
-            (-1, 0),
-            (-1, 1),
-            (0, -1),
-            (0, 1),
-            (1, -1),
-            (1, 0),
-            (1, 1),
-        ] {
+        let directions: &[(isize, isize)] = match settings.neighbourhood {
+            Neighbourhood::Four => &[(-1, 0), (0, -1), (0, 1), (1, 0)],
+            _ => &[
+                (-1, -1),
+                (-1, 0),
+                (-1, 1),
+                (0, -1),
+                (0, 1),
+                (1, -1),
+                (1, 0),
+                (1, 1),
+            ],
+        };
+        let seats_only = settings.neighbourhood == Neighbourhood::LineOfSight;
+        let range = if seats_only { settings.range } else { Some(1) };
+
+        for &dir in directions {
             let mut cursor = (row, col);
+            let mut distance = 0;
             while let Some((x, y)) = look(cursor, dir) {
+                distance += 1;
+                if range.is_some_and(|range| distance > range) {
+                    break;
+                }
                 let i = (x * cols + y) as usize;
-                match (&self.spaces[i], seats_only) {
-                    (seat, false)
-                    | (seat @ Space::OccupiedSeat, true)
-                    | (seat @ Space::EmptySeat, true) => {
-                        neighbors.push(seat.clone());
-                        break;
-                    }
-                    _ => (),
+                if !seats_only || self.spaces[i] != Space::Floor {
+                    neighbors.push(i);
+                    break;
                 }
                 cursor = (x, y);
             }
@@ -90,34 +205,85 @@ impl Map {
     }
 
     fn tick(&mut self, settings: &TickSettings) -> bool {
-        let mut new_spaces = Vec::with_capacity(self.spaces.len());
+        let visibility = match self.visibility.take() {
+            Some(visibility) if visibility.matches(settings) => visibility,
+            _ => Visibility::new(self, settings),
+        };
+        self.next.clear();
         let mut changed = false;
-        for (i, space) in self.spaces.iter().enumerate() {
-            let occupied_neighbors = self
-                .visible_seats(i, settings.seats_only)
+        for (i, &space) in self.spaces.iter().enumerate() {
+            let occupied_neighbors = visibility
+                .of(i)
                 .iter()
-                .filter(|space| space.is_occupied())
+                .filter(|&&j| self.spaces[j].is_occupied())
                 .count();
-            match (space, occupied_neighbors) {
-                (Space::EmptySeat, 0) => {
-                    changed = true;
-                    new_spaces.push(Space::OccupiedSeat);
-                }
-                (Space::OccupiedSeat, x) if x >= settings.occupant_threshold => {
-                    changed = true;
-                    new_spaces.push(Space::EmptySeat);
-                }
-                (space, _) => {
-                    new_spaces.push(space.clone());
-                }
-            }
+            let new = match (space, occupied_neighbors) {
+                (Space::EmptySeat, x) if x <= settings.occupy_threshold => Space::OccupiedSeat,
+                (Space::OccupiedSeat, x) if x >= settings.vacate_threshold => Space::EmptySeat,
+                (space, _) => space,
+            };
+            changed |= new != space;
+            self.next.push(new);
         }
-        self.spaces = new_spaces;
+        std::mem::swap(&mut self.spaces, &mut self.next);
+        self.visibility = Some(visibility);
         changed
     }
 
-    fn stabilize(&mut self, settings: &TickSettings) {
-        while self.tick(settings) {}
+    fn layout_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.spaces.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The layout `rounds` rounds on from `start`, without touching this map.
+    fn replay(&mut self, start: &[Space], rounds: usize, settings: &TickSettings) -> Vec<Space> {
+        let mut replay = Map {
+            rows: self.rows,
+            columns: self.columns,
+            spaces: start.to_vec(),
+            next: vec![],
+            visibility: self.visibility.take(),
+        };
+        for _ in 0..rounds {
+            replay.tick(settings);
+        }
+        self.visibility = replay.visibility.take();
+        replay.spaces
+    }
+
+    /// Ticks until the layout repeats, returning the cycle it fell into, or
+    /// `None` if that takes more than `max_rounds`. The map is left at the
+    /// first repeat.
+    ///
+    /// Only a hash of each round is kept. When one comes up again, the earlier
+    /// round is replayed from the start to rule out a collision.
+    fn stabilize(&mut self, settings: &TickSettings, max_rounds: Option<usize>) -> Option<Cycle> {
+        let start = self.spaces.clone();
+        let mut seen: HashMap<u64, Vec<usize>> = HashMap::new();
+        seen.insert(self.layout_hash(), vec![0]);
+        for round in 1.. {
+            if max_rounds.is_some_and(|max| round > max) {
+                break;
+            }
+            if !self.tick(settings) {
+                return Some(Cycle {
+                    entry: round - 1,
+                    length: 1,
+                });
+            }
+            let rounds = seen.entry(self.layout_hash()).or_default();
+            for &entry in rounds.iter() {
+                if self.replay(&start, entry, settings) == self.spaces {
+                    return Some(Cycle {
+                        entry,
+                        length: round - entry,
+                    });
+                }
+            }
+            rounds.push(round);
+        }
+        None
     }
 
     fn occupied_count(&self) -> usize {
@@ -149,6 +315,7 @@ impl FromStr for Map {
             rows,
             columns,
             spaces,
+            ..Default::default()
         })
     }
 }
@@ -167,24 +334,84 @@ impl Display for Map {
     }
 }
 
+fn report(label: &str, map: &mut Map, settings: &TickSettings, max_rounds: Option<usize>) {
+    match map.stabilize(settings, max_rounds) {
+        Some(Cycle { length: 1, .. }) => {
+            println!("{} occupied: {}", label, map.occupied_count())
+        }
+        Some(Cycle { entry, length }) => println!(
+            "{} cycles every {} rounds from round {} ({} occupied)",
+            label,
+            length,
+            entry,
+            map.occupied_count()
+        ),
+        None => println!(
+            "{} still changing after {} rounds",
+            label,
+            max_rounds.unwrap_or(0)
+        ),
+    }
+}
+
+fn value<T>(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match args.next().map(|n| n.parse::<T>()) {
+        Some(Ok(n)) => Ok(n),
+        Some(Err(e)) => Err(format!("Error: {}: {}", flag, e)),
+        None => Err(format!("Error: {} needs a value", flag)),
+    }
+}
+
 fn main() {
+    let mut max_rounds = None;
+    let mut custom: Option<TickSettings> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "--max-rounds" => value(&mut args, &arg).map(|n| max_rounds = Some(n)),
+            // Any rule flag switches to a single run, starting from part 1's rules
+            "--shape" => value(&mut args, &arg)
+                .map(|shape| custom.get_or_insert_with(Default::default).neighbourhood = shape),
+            "--range" => value(&mut args, &arg)
+                .map(|n| custom.get_or_insert_with(Default::default).range = Some(n)),
+            "--occupy" => value(&mut args, &arg)
+                .map(|n| custom.get_or_insert_with(Default::default).occupy_threshold = n),
+            "--vacate" => value(&mut args, &arg)
+                .map(|n| custom.get_or_insert_with(Default::default).vacate_threshold = n),
+            _ => Err(format!("Error: unknown argument {:?}", arg)),
+        };
+        if let Err(e) = parsed {
+            return eprintln!("{}", e);
+        }
+    }
+    if let Some(settings) = &custom {
+        if settings.range.is_some() && settings.neighbourhood != Neighbourhood::LineOfSight {
+            return eprintln!("Error: --range only applies to --shape sight");
+        }
+    }
     let input = std::fs::read_to_string("input.txt").unwrap();
 
-    let settings1 = TickSettings {
-        seats_only: false,
-        occupant_threshold: 4,
-    };
+    if let Some(settings) = custom {
+        let mut map = input.parse::<Map>().unwrap();
+        report("Custom", &mut map, &settings, max_rounds);
+        return;
+    }
+
+    let settings1 = TickSettings::default();
     let mut map1 = input.parse::<Map>().unwrap();
-    map1.stabilize(&settings1);
-    println!("Part 1 occupied: {}", map1.occupied_count());
+    report("Part 1", &mut map1, &settings1, max_rounds);
 
     let settings2 = TickSettings {
-        seats_only: true,
-        occupant_threshold: 5,
+        neighbourhood: Neighbourhood::LineOfSight,
+        vacate_threshold: 5,
+        ..Default::default()
     };
     let mut map2 = input.parse::<Map>().unwrap();
-    map2.stabilize(&settings2);
-    println!("Part 2 occupied: {}", map2.occupied_count());
+    report("Part 2", &mut map2, &settings2, max_rounds);
 }
 
 #[cfg(test)]
@@ -223,18 +450,127 @@ mod tests {
                      #.LL";
 
         let map = input.parse::<Map>().unwrap();
-        assert_eq!(map.visible_seats(10, false), to_neighbs("..L.#.LL"));
-        assert_eq!(map.visible_seats(0, false), to_neighbs(".#."));
-        assert_eq!(map.visible_seats(0, true), to_neighbs("##L"));
+        assert_eq!(
+            map.visible_seats(10, Neighbourhood::Eight),
+            to_neighbs("..L.#.LL")
+        );
+        assert_eq!(
+            map.visible_seats(0, Neighbourhood::Eight),
+            to_neighbs(".#.")
+        );
+        assert_eq!(
+            map.visible_seats(0, Neighbourhood::LineOfSight),
+            to_neighbs("##L")
+        );
 
-        assert_eq!(map.visible_seats(15, false), to_neighbs(".#L"));
-        assert_eq!(map.visible_seats(15, true), to_neighbs("L#L"));
+        assert_eq!(
+            map.visible_seats(15, Neighbourhood::Eight),
+            to_neighbs(".#L")
+        );
+        assert_eq!(
+            map.visible_seats(15, Neighbourhood::LineOfSight),
+            to_neighbs("L#L")
+        );
 
-        assert_eq!(map.visible_seats(7, false), to_neighbs("#...#"));
-        assert_eq!(map.visible_seats(7, true), to_neighbs("###"));
+        assert_eq!(
+            map.visible_seats(7, Neighbourhood::Eight),
+            to_neighbs("#...#")
+        );
+        assert_eq!(
+            map.visible_seats(7, Neighbourhood::LineOfSight),
+            to_neighbs("###")
+        );
 
-        assert_eq!(map.visible_seats(12, false), to_neighbs("L.."));
-        assert_eq!(map.visible_seats(12, true), to_neighbs("LL"));
+        assert_eq!(
+            map.visible_seats(12, Neighbourhood::Eight),
+            to_neighbs("L..")
+        );
+        assert_eq!(
+            map.visible_seats(12, Neighbourhood::LineOfSight),
+            to_neighbs("LL")
+        );
+    }
+
+    #[test]
+    fn test_neighbourhoods() {
+        let input = "#.#L.\n\
+                     .....\n\
+                     L.L.#\n\
+                     .....\n\
+                     #.L.L";
+        let map = input.parse::<Map>().unwrap();
+        let visible = |settings: TickSettings| {
+            let seats = Visibility::new(&map, &settings)
+                .of(12)
+                .iter()
+                .map(|&i| map.spaces[i])
+                .collect::<Vec<_>>();
+            to_neighbor_string(&seats)
+        };
+        assert_eq!(
+            visible(TickSettings {
+                neighbourhood: Neighbourhood::Four,
+                ..Default::default()
+            }),
+            "...."
+        );
+        let sight = TickSettings {
+            neighbourhood: Neighbourhood::LineOfSight,
+            ..Default::default()
+        };
+        assert_eq!(visible(sight.clone()), "##L##LL");
+        assert_eq!(
+            visible(TickSettings {
+                range: Some(2),
+                ..sight.clone()
+            }),
+            "##L##LL"
+        );
+        assert_eq!(
+            visible(TickSettings {
+                range: Some(1),
+                ..sight
+            }),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_custom_rules() {
+        let input = "LLL\n\
+                     LLL";
+        let mut map = input.parse::<Map>().unwrap();
+        let settings = TickSettings {
+            neighbourhood: Neighbourhood::Four,
+            occupy_threshold: 1,
+            vacate_threshold: 3,
+            ..Default::default()
+        };
+        assert!(map.tick(&settings));
+        assert_eq!(map.to_string(), "###\n###");
+        assert!(map.tick(&settings));
+        assert_eq!(map.to_string(), "#L#\n#L#");
+        assert_eq!(
+            map.stabilize(&settings, None),
+            Some(Cycle {
+                entry: 0,
+                length: 1
+            })
+        );
+
+        // The cached visibility follows the settings it's ticked with
+        let eight = TickSettings {
+            occupy_threshold: 2,
+            vacate_threshold: 9,
+            ..Default::default()
+        };
+        assert!(!map.tick(&eight));
+        let four = TickSettings {
+            neighbourhood: Neighbourhood::Four,
+            ..eight
+        };
+        assert!(map.tick(&four));
+        assert_eq!(map.to_string(), "###\n###");
     }
 
     #[test]
@@ -251,10 +587,13 @@ mod tests {
         let map = input.parse::<Map>().unwrap();
         assert_eq!(map.spaces[39], Space::EmptySeat);
         assert_eq!(
-            to_neighbor_string(&map.visible_seats(39, false)),
+            to_neighbor_string(&map.visible_seats(39, Neighbourhood::Eight)),
             "...#...#"
         );
-        assert_eq!(to_neighbor_string(&map.visible_seats(39, true)), "########");
+        assert_eq!(
+            to_neighbor_string(&map.visible_seats(39, Neighbourhood::LineOfSight)),
+            "########"
+        );
     }
 
     #[test]
@@ -270,10 +609,7 @@ mod tests {
                      L.LLLLLL.L\n\
                      L.LLLLL.LL";
         let mut map = input.parse::<Map>().unwrap();
-        let settings = TickSettings {
-            seats_only: false,
-            occupant_threshold: 4,
-        };
+        let settings = TickSettings::default();
         assert!(map.tick(&settings));
         assert_eq!(
             format!("{}", map),
@@ -305,7 +641,13 @@ mod tests {
              #.LLLLLL.L\n\
              #.#LLLL.##"
         );
-        map.stabilize(&settings);
+        assert_eq!(
+            map.stabilize(&settings, None),
+            Some(Cycle {
+                entry: 3,
+                length: 1
+            })
+        );
 
         let stabilized = "#.#L.L#.##\n\
                           #LLL#LL.L#\n\
@@ -336,10 +678,54 @@ mod tests {
                      L.LLLLL.LL";
         let mut map = input.parse::<Map>().unwrap();
         let settings = TickSettings {
-            seats_only: true,
-            occupant_threshold: 5,
+            neighbourhood: Neighbourhood::LineOfSight,
+            vacate_threshold: 5,
+            ..Default::default()
         };
-        map.stabilize(&settings);
+        assert!(map.stabilize(&settings, None).is_some());
         assert_eq!(map.occupied_count(), 26);
     }
+
+    #[test]
+    fn test_stabilize_limits() {
+        let input = "L.LL.LL.LL\n\
+                     LLLLLLL.LL\n\
+                     L.L.L..L..\n\
+                     LLLL.LL.LL";
+        let settings = TickSettings::default();
+        let mut map = input.parse::<Map>().unwrap();
+        assert_eq!(map.stabilize(&settings, Some(1)), None);
+        let mut map = input.parse::<Map>().unwrap();
+        let cycle = map.stabilize(&settings, None).unwrap();
+        let mut map = input.parse::<Map>().unwrap();
+        assert_eq!(map.stabilize(&settings, Some(cycle.entry + 1)), Some(cycle));
+        // Staying put still takes a round to confirm
+        assert_eq!(map.stabilize(&settings, Some(0)), None);
+        assert_eq!(
+            map.stabilize(&settings, Some(1)),
+            Some(Cycle {
+                entry: 0,
+                length: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_stabilize_cycle() {
+        // Occupied seats always empty, so the seats blink on and off
+        let settings = TickSettings {
+            vacate_threshold: 0,
+            ..Default::default()
+        };
+        let mut map = "L.L\n.#.".parse::<Map>().unwrap();
+        assert_eq!(
+            map.stabilize(&settings, None),
+            Some(Cycle {
+                entry: 1,
+                length: 2
+            })
+        );
+        assert_eq!(map.to_string(), "L.L\n.L.");
+        assert_eq!(map.stabilize(&settings, Some(1)), None);
+    }
 }